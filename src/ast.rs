@@ -1,4 +1,4 @@
-use crate::token::Token;
+use crate::{shared::Shared, token::Token};
 use std::fmt;
 
 #[derive(Clone)]
@@ -48,11 +48,28 @@ pub enum Expr {
         name: Token,
         value: Box<Expr>,
     },
+    // `name ??= value`, which only evaluates and assigns `value` when the
+    // variable holds nil.
+    DefaultAssign {
+        name: Token,
+        value: Box<Expr>,
+    },
     Call {
         callee: Box<Expr>,
         paren: Token,
         arguments: Vec<Expr>,
     },
+    Is {
+        value: Box<Expr>,
+        type_name: Token,
+    },
+    Interpolation {
+        parts: Vec<Expr>,
+    },
+    When {
+        arms: Vec<(Expr, Expr)>,
+        else_arm: Option<Box<Expr>>,
+    },
 }
 
 impl Expr {
@@ -79,11 +96,23 @@ impl Expr {
             Expr::Assign { name, value } => {
                 visitor.visit_assign_expr(name, value)
             }
+            Expr::DefaultAssign { name, value } => {
+                visitor.visit_default_assign_expr(name, value)
+            }
             Expr::Call {
                 callee,
                 paren,
                 arguments,
             } => visitor.visit_call_expr(callee, paren, arguments),
+            Expr::Is { value, type_name } => {
+                visitor.visit_is_expr(value, type_name)
+            }
+            Expr::Interpolation { parts } => {
+                visitor.visit_interpolation_expr(parts)
+            }
+            Expr::When { arms, else_arm } => {
+                visitor.visit_when_expr(arms, else_arm.as_deref())
+            }
         }
     }
 }
@@ -110,11 +139,23 @@ pub mod expr {
         fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> R;
         fn visit_variable_expr(&mut self, name: &Token) -> R;
         fn visit_assign_expr(&mut self, name: &Token, value: &Expr) -> R;
+        fn visit_default_assign_expr(
+            &mut self,
+            name: &Token,
+            value: &Expr,
+        ) -> R;
         fn visit_call_expr(
             &mut self,
             callee: &Expr,
             paren: &Token,
-            arguments: &Vec<Expr>,
+            arguments: &[Expr],
+        ) -> R;
+        fn visit_is_expr(&mut self, value: &Expr, type_name: &Token) -> R;
+        fn visit_interpolation_expr(&mut self, parts: &[Expr]) -> R;
+        fn visit_when_expr(
+            &mut self,
+            arms: &[(Expr, Expr)],
+            else_arm: Option<&Expr>,
         ) -> R;
     }
 }
@@ -132,8 +173,16 @@ pub enum Stmt {
     },
     Var {
         name: Token,
+        // The type name after `name:`, if any.
+        annotation: Option<Token>,
         initializer: Option<Expr>,
     },
+    Const {
+        name: Token,
+        initializer: Expr,
+    },
+    // Stands in for a declaration that failed to parse and was skipped
+    // during recovery; it does nothing.
     Nil,
     If {
         condition: Expr,
@@ -141,18 +190,37 @@ pub enum Stmt {
         else_branch: Box<Option<Stmt>>,
     },
     While {
+        // The `while` or `for` keyword, for lints about the loop.
+        keyword: Token,
         condition: Expr,
         body: Box<Stmt>,
     },
     Function {
         name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        // Shared with every function value the declaration creates.
+        params: Shared<[Token]>,
+        body: Shared<[Stmt]>,
     },
     Return {
         keyword: Token,
         value: Option<Expr>,
     },
+    Assert {
+        keyword: Token,
+        condition: Expr,
+        // The condition as written, for the failure message.
+        text: String,
+    },
+    // A block that only runs while the interpreter is in debug mode.
+    Debug {
+        statements: Vec<Stmt>,
+    },
+    // `test "name" { ... }`, skipped by ordinary runs and run one by one
+    // by `Lox::test_file`.
+    Test {
+        name: Token,
+        body: Vec<Stmt>,
+    },
 }
 
 impl Stmt {
@@ -163,58 +231,88 @@ impl Stmt {
                 visitor.visit_expression_stmt(expression)
             }
             Stmt::Print { expression } => visitor.visit_print_stmt(expression),
-            Stmt::Var { name, initializer } => {
-                visitor.visit_var_stmt(name, initializer)
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            } => visitor.visit_var_stmt(name, annotation.as_ref(), initializer),
+            Stmt::Const { name, initializer } => {
+                visitor.visit_const_stmt(name, initializer)
             }
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
             } => visitor.visit_if_stmt(condition, then_branch, else_branch),
-            Stmt::While { condition, body } => {
-                visitor.visit_while_stmt(condition, body)
-            }
-            Stmt::Nil => unimplemented!(),
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+            } => visitor.visit_while_stmt(keyword, condition, body),
+            Stmt::Nil => visitor.visit_nil_stmt(),
             Stmt::Function { name, params, body } => {
                 visitor.visit_function_stmt(name, params, body)
             }
             Stmt::Return { keyword, value } => {
                 visitor.visit_return_stmt(keyword, value)
             }
+            Stmt::Assert {
+                keyword,
+                condition,
+                text,
+            } => visitor.visit_assert_stmt(keyword, condition, text),
+            Stmt::Debug { statements } => visitor.visit_debug_stmt(statements),
+            Stmt::Test { name, body } => visitor.visit_test_stmt(name, body),
         }
     }
 }
 
 pub mod stmt {
     use super::{Expr, Stmt};
-    use crate::token::Token;
+    use crate::{shared::Shared, token::Token};
 
     pub trait Visitor<R> {
-        fn visit_block_stmt(&mut self, statements: &Vec<Stmt>) -> R;
+        fn visit_block_stmt(&mut self, statements: &[Stmt]) -> R;
         fn visit_expression_stmt(&mut self, expression: &Expr) -> R;
         fn visit_print_stmt(&mut self, expression: &Expr) -> R;
         fn visit_var_stmt(
             &mut self,
             name: &Token,
+            annotation: Option<&Token>,
             initializer: &Option<Expr>,
         ) -> R;
+        fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> R;
         fn visit_if_stmt(
             &mut self,
             condition: &Expr,
             then_branch: &Stmt,
             else_branch: &Option<Stmt>,
         ) -> R;
-        fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> R;
+        fn visit_while_stmt(
+            &mut self,
+            keyword: &Token,
+            condition: &Expr,
+            body: &Stmt,
+        ) -> R;
         fn visit_function_stmt(
             &mut self,
             name: &Token,
-            params: &Vec<Token>,
-            body: &Vec<Stmt>,
+            params: &Shared<[Token]>,
+            body: &Shared<[Stmt]>,
         ) -> R;
         fn visit_return_stmt(
             &mut self,
             keyword: &Token,
             value: &Option<Expr>,
         ) -> R;
+        fn visit_assert_stmt(
+            &mut self,
+            keyword: &Token,
+            condition: &Expr,
+            text: &str,
+        ) -> R;
+        fn visit_debug_stmt(&mut self, statements: &[Stmt]) -> R;
+        fn visit_nil_stmt(&mut self) -> R;
+        fn visit_test_stmt(&mut self, name: &Token, body: &[Stmt]) -> R;
     }
 }
@@ -1,5 +1,6 @@
 use crate::{
-    ast::{expr, Expr, LiteralValue},
+    ast::{expr, stmt, Expr, LiteralValue, Stmt},
+    shared::Shared,
     token::Token,
 };
 
@@ -10,15 +11,19 @@ impl AstPrinter {
         expr.accept(self)
     }
 
+    pub fn print_stmt(&mut self, stmt: &Stmt) -> String {
+        stmt.accept(self)
+    }
+
     fn parenthesize(&mut self, name: String, exprs: Vec<&Expr>) -> String {
         let mut r = String::new();
-        r.push_str("(");
+        r.push('(');
         r.push_str(&name);
         for e in &exprs {
-            r.push_str(" ");
+            r.push(' ');
             r.push_str(&e.accept(self));
         }
-        r.push_str(")");
+        r.push(')');
         r
     }
 }
@@ -53,6 +58,14 @@ impl expr::Visitor<String> for AstPrinter {
         self.parenthesize(name.lexeme.clone(), vec![value])
     }
 
+    fn visit_default_assign_expr(
+        &mut self,
+        name: &Token,
+        value: &Expr,
+    ) -> String {
+        self.parenthesize(format!("??= {}", name.lexeme), vec![value])
+    }
+
     fn visit_logical_expr(
         &mut self,
         left: &Expr,
@@ -64,29 +77,204 @@ impl expr::Visitor<String> for AstPrinter {
 
     fn visit_call_expr(
         &mut self,
-        _callee: &Expr,
+        callee: &Expr,
         _paren: &Token,
-        _arguments: &Vec<Expr>,
+        arguments: &[Expr],
+    ) -> String {
+        let mut exprs = vec![callee];
+        exprs.extend(arguments);
+        self.parenthesize("call".to_string(), exprs)
+    }
+
+    fn visit_is_expr(&mut self, value: &Expr, type_name: &Token) -> String {
+        self.parenthesize(format!("is {}", type_name.lexeme), vec![value])
+    }
+
+    fn visit_interpolation_expr(&mut self, parts: &[Expr]) -> String {
+        self.parenthesize("interpolate".to_string(), parts.iter().collect())
+    }
+
+    fn visit_when_expr(
+        &mut self,
+        arms: &[(Expr, Expr)],
+        else_arm: Option<&Expr>,
+    ) -> String {
+        let mut r = String::from("(when");
+        for (condition, value) in arms {
+            r.push(' ');
+            let condition = condition.accept(self);
+            r.push_str(&self.parenthesize(condition, vec![value]));
+        }
+        if let Some(else_arm) = else_arm {
+            r.push(' ');
+            r.push_str(&self.parenthesize("else".to_string(), vec![else_arm]));
+        }
+        r.push(')');
+        r
+    }
+}
+
+impl stmt::Visitor<String> for AstPrinter {
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> String {
+        let mut r = String::from("(block");
+        for statement in statements {
+            r.push(' ');
+            r.push_str(&statement.accept(self));
+        }
+        r.push(')');
+        r
+    }
+
+    fn visit_test_stmt(&mut self, name: &Token, body: &[Stmt]) -> String {
+        let block = self.visit_block_stmt(body);
+        format!("(test \"{}\" {})", name.lexeme, block)
+    }
+
+    fn visit_nil_stmt(&mut self) -> String {
+        "(nil)".to_string()
+    }
+
+    fn visit_debug_stmt(&mut self, statements: &[Stmt]) -> String {
+        let block = self.visit_block_stmt(statements);
+        format!("(debug {})", block)
+    }
+
+    fn visit_expression_stmt(&mut self, expression: &Expr) -> String {
+        self.parenthesize(";".to_string(), vec![expression])
+    }
+
+    fn visit_print_stmt(&mut self, expression: &Expr) -> String {
+        self.parenthesize("print".to_string(), vec![expression])
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        annotation: Option<&Token>,
+        initializer: &Option<Expr>,
+    ) -> String {
+        let name = match annotation {
+            Some(annotation) => {
+                format!("{}: {}", name.lexeme, annotation.lexeme)
+            }
+            None => name.lexeme.clone(),
+        };
+        match initializer {
+            Some(init) => {
+                self.parenthesize(format!("var {} =", name), vec![init])
+            }
+            None => format!("(var {})", name),
+        }
+    }
+
+    fn visit_const_stmt(&mut self, name: &Token, initializer: &Expr) -> String {
+        self.parenthesize(format!("const {} =", name.lexeme), vec![initializer])
+    }
+
+    // An else branch that is itself an `if` continues the same list as
+    // `elif`, so `else if` chains print flat instead of nested.
+    fn visit_if_stmt(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Stmt>,
     ) -> String {
-        unimplemented!()
+        let mut r = format!(
+            "(if {} {}",
+            condition.accept(self),
+            then_branch.accept(self)
+        );
+        let mut else_branch = else_branch;
+        while let Some(branch) = else_branch {
+            match branch {
+                Stmt::If {
+                    condition,
+                    then_branch,
+                    else_branch: next,
+                } => {
+                    r.push_str(&format!(
+                        " elif {} {}",
+                        condition.accept(self),
+                        then_branch.accept(self)
+                    ));
+                    else_branch = next;
+                }
+                other => {
+                    r.push_str(&format!(" else {}", other.accept(self)));
+                    break;
+                }
+            }
+        }
+        r.push(')');
+        r
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        body: &Stmt,
+    ) -> String {
+        format!("(while {} {})", condition.accept(self), body.accept(self))
+    }
+
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        params: &Shared<[Token]>,
+        body: &Shared<[Stmt]>,
+    ) -> String {
+        let params: Vec<&str> =
+            params.iter().map(|param| param.lexeme.as_str()).collect();
+        let mut r = format!("(fun {}({})", name.lexeme, params.join(" "));
+        for statement in body.iter() {
+            r.push(' ');
+            r.push_str(&statement.accept(self));
+        }
+        r.push(')');
+        r
+    }
+
+    fn visit_assert_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        _text: &str,
+    ) -> String {
+        self.parenthesize("assert".to_string(), vec![condition])
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        _keyword: &Token,
+        value: &Option<Expr>,
+    ) -> String {
+        match value {
+            Some(value) => self.parenthesize("return".to_string(), vec![value]),
+            None => "(return)".to_string(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::token::{Token, TokenType};
+    use crate::{
+        lexer::Lexer,
+        parser::Parser,
+        token::{Token, TokenType},
+    };
 
     #[test]
     fn test_printer() {
         let expression = Expr::Binary {
             left: Box::new(Expr::Unary {
-                operator: Token::new(TokenType::Minus, "-", 1),
+                operator: Token::new(TokenType::Minus, "-", 1, 1),
                 right: Box::new(Expr::Literal {
                     value: LiteralValue::Number(123.0),
                 }),
             }),
-            operator: Token::new(TokenType::Star, "*", 1),
+            operator: Token::new(TokenType::Star, "*", 1, 6),
             right: Box::new(Expr::Grouping {
                 expression: Box::new(Expr::Literal {
                     value: LiteralValue::Number(45.67),
@@ -97,4 +285,24 @@ mod tests {
 
         assert_eq!(printer.print(expression), "(* (- 123) (group 45.67))");
     }
+
+    #[test]
+    fn test_else_if_chain() {
+        let source = r#"
+            if (n < 0) print "negative";
+            else if (n == 0) { print "zero"; }
+            else print "positive";
+        "#;
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut printer = AstPrinter;
+
+        assert_eq!(
+            printer.print_stmt(&statements[0]),
+            "(if (< n 0) (print negative) \
+             elif (== n 0) (block (print zero)) \
+             else (print positive))"
+        );
+    }
 }
@@ -0,0 +1,145 @@
+use crate::{
+    interpreter::InterpretError,
+    lexer::LexError,
+    parser::ParseError,
+    resolver::{ResolveError, ResolveWarning},
+    token::Token,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// One error or warning from any phase, in a shape tooling can consume
+// without matching on each phase's own type. `column` is `None` for lex
+// errors, which only track lines.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: Option<usize>,
+    pub message: String,
+    pub code: &'static str,
+}
+
+impl Diagnostic {
+    fn at(
+        severity: Severity,
+        token: &Token,
+        message: String,
+        code: &'static str,
+    ) -> Self {
+        Diagnostic {
+            severity,
+            line: token.line,
+            column: Some(token.column),
+            message,
+            code,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(error: LexError) -> Self {
+        let (line, code) = match &error {
+            LexError::UnexpectedCharacter { line, .. } => {
+                (*line, "unexpected-character")
+            }
+            LexError::UnterminatedString { line, .. } => {
+                (*line, "unterminated-string")
+            }
+            LexError::InvalidEscape { line, .. } => (*line, "invalid-escape"),
+            LexError::InvalidDirective { line } => (*line, "invalid-directive"),
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            line,
+            column: None,
+            message: error.to_string(),
+            code,
+        }
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Self {
+        let code = match &error {
+            ParseError::UnexpectedToken { .. } => "unexpected-token",
+            ParseError::InvalidAssignment { .. } => "invalid-assignment",
+            ParseError::MissingSemicolon { .. } => "missing-semicolon",
+            ParseError::ExpectedBlock { .. } => "expected-block",
+        };
+        Diagnostic::at(Severity::Error, error.token(), error.to_string(), code)
+    }
+}
+
+impl From<ResolveError> for Diagnostic {
+    fn from(error: ResolveError) -> Self {
+        let code = match &error {
+            ResolveError::AlreadyDeclared { .. } => "already-declared",
+            ResolveError::ReadInOwnInitializer { .. } => {
+                "read-in-own-initializer"
+            }
+            ResolveError::TopLevelReturn { .. } => "top-level-return",
+            ResolveError::AssignToConst { .. } => "assign-to-const",
+        };
+        Diagnostic::at(Severity::Error, error.token(), error.to_string(), code)
+    }
+}
+
+impl From<ResolveWarning> for Diagnostic {
+    fn from(warning: ResolveWarning) -> Self {
+        let (token, code) = match &warning {
+            ResolveWarning::PrintingCallable { token } => {
+                (token, "printing-callable")
+            }
+            ResolveWarning::ShadowsNative { token } => {
+                (token, "shadows-native")
+            }
+            ResolveWarning::InfiniteLoop { token } => (token, "infinite-loop"),
+        };
+        Diagnostic::at(Severity::Warning, token, warning.to_string(), code)
+    }
+}
+
+impl From<InterpretError> for Diagnostic {
+    fn from(error: InterpretError) -> Self {
+        let code = match &error {
+            InterpretError::TypeError { .. }
+            | InterpretError::Conversion { .. } => "type-error",
+            InterpretError::UndefinedError { .. } => "undefined",
+            InterpretError::UseBeforeAssignment { .. } => {
+                "use-before-assignment"
+            }
+            InterpretError::AssertionFailed { .. } => "assertion-failed",
+            InterpretError::TypeMismatch { .. } => "type-mismatch",
+            InterpretError::AssignToConst { .. } => "assign-to-const",
+            InterpretError::Return { .. } => "return",
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            line: error.token().map_or(0, |token| token.line),
+            column: error.token().map(|token| token.column),
+            message: error.to_string(),
+            code,
+        }
+    }
+}
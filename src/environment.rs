@@ -1,46 +1,88 @@
 use crate::object::Object;
 use crate::{
     interpreter::{InterpretError, Result},
+    shared::{Lock, Shared},
     token::Token,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::collections::HashMap;
+
+// Environments alive on this thread, to spot ones kept alive by a cycle: a
+// closure stored in the environment it captures holds a strong reference
+// back to it, so neither is ever dropped. Holding the enclosing environment
+// through a `Weak` would break the cycle, but then a closure returned from
+// its defining call would lose its scope, so captured environments would
+// need an owner such as a collector.
+#[cfg(debug_assertions)]
+thread_local! {
+    static LIVE: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
 
 #[derive(Debug)]
 pub struct Environment {
-    enclosing: Option<Rc<RefCell<Environment>>>,
+    enclosing: Option<Shared<Lock<Environment>>>,
     values: HashMap<String, Object>,
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
+        Self::count(1);
         Environment {
             enclosing: None,
             values: HashMap::new(),
         }
     }
 
-    pub fn from(enclosing: &Rc<RefCell<Environment>>) -> Self {
+    pub fn from(enclosing: &Shared<Lock<Environment>>) -> Self {
+        Self::count(1);
         Environment {
-            enclosing: Some(Rc::clone(enclosing)),
+            enclosing: Some(Shared::clone(enclosing)),
             values: HashMap::new(),
         }
     }
 
+    #[cfg(debug_assertions)]
+    pub fn live() -> usize {
+        LIVE.with(|live| live.get())
+    }
+
+    fn count(delta: isize) {
+        #[cfg(debug_assertions)]
+        LIVE.with(|live| live.set(live.get().saturating_add_signed(delta)));
+        #[cfg(not(debug_assertions))]
+        let _ = delta;
+    }
+
+    // Readies an environment taken from the interpreter's free-list for
+    // reuse, keeping the capacity of its value map.
+    pub fn reset(&mut self, enclosing: Option<&Shared<Lock<Environment>>>) {
+        self.enclosing = enclosing.map(Shared::clone);
+        self.values.clear();
+    }
+
     pub fn define(&mut self, name: String, value: Object) {
         self.values.insert(name, value);
     }
 
+    pub fn values(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.values.iter()
+    }
+
     pub fn get(&self, name: &Token) -> Result<Object> {
-        if let Some(value) = self.values.get(&name.lexeme) {
-            return Ok(value.clone());
-        }
-        if let Some(enclosing) = &self.enclosing {
-            return enclosing.borrow().get(name);
+        self.find(&name.lexeme).ok_or_else(|| self.undefined(name))
+    }
+
+    fn find(&self, key: &str) -> Option<Object> {
+        match (self.values.get(key), &self.enclosing) {
+            (Some(value), _) => Some(value.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().find(key),
+            (None, None) => None,
         }
-        Err(InterpretError::UndefinedError {
-            token: name.clone(),
-            message: format!("Undefined variable '{}'.", name.lexeme),
-        })
     }
 
     pub fn get_at(&self, distance: usize, name: &Token) -> Object {
@@ -49,22 +91,53 @@ impl Environment {
             Some(env) => env.borrow().values.get(key).cloned(),
             None => self.values.get(key).cloned(),
         };
-        dbg!(&obj, distance, name, key, &self.values, &self.enclosing);
-        obj.expect(&format!("Undefined variable '{}'", key))
+        obj.unwrap_or_else(|| panic!("Undefined variable '{}'", key))
     }
 
     pub fn assign(&mut self, name: &Token, value: Object) -> Result<()> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.to_string(), value);
-            return Ok(());
+        if self.replace(&name.lexeme, value) {
+            Ok(())
+        } else {
+            Err(self.undefined(name))
+        }
+    }
+
+    fn replace(&mut self, key: &str, value: Object) -> bool {
+        if let Some(slot) = self.values.get_mut(key) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().replace(key, value),
+            None => false,
+        }
+    }
+
+    // Suggests the closest name visible from this environment, so a
+    // misspelled local is matched against the locals around it as well as
+    // the globals.
+    pub fn undefined(&self, name: &Token) -> InterpretError {
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        let mut next = self.enclosing.clone();
+        while let Some(env) = next {
+            let env = env.borrow();
+            names.extend(env.values.keys().cloned());
+            next = env.enclosing.clone();
         }
-        if let Some(enclosing) = &self.enclosing {
-            return enclosing.borrow_mut().assign(name, value);
+        let mut message = format!("Undefined variable '{}'.", name.lexeme);
+        let limit = (name.lexeme.chars().count() / 3).clamp(1, 2);
+        let closest = names
+            .iter()
+            .map(|key| (edit_distance(&name.lexeme, key), key))
+            .filter(|(distance, _)| *distance <= limit)
+            .min();
+        if let Some((_, key)) = closest {
+            message.push_str(&format!(" Did you mean '{}'?", key));
         }
-        Err(InterpretError::UndefinedError {
+        InterpretError::UndefinedError {
             token: name.clone(),
-            message: format!("Undefined variable '{}'.", name.lexeme),
-        })
+            message,
+        }
     }
 
     pub fn assign_at(&mut self, distance: usize, name: &Token, value: Object) {
@@ -75,15 +148,38 @@ impl Environment {
         };
     }
 
-    fn ancestor(&self, distance: usize) -> Option<Rc<RefCell<Environment>>> {
-        let mut environment = None;
+    fn ancestor(&self, distance: usize) -> Option<Shared<Lock<Environment>>> {
+        let mut environment: Option<Shared<Lock<Environment>>> = None;
         for i in 0..distance {
-            let parent = self
-                .enclosing
-                .clone()
-                .expect(&format!("No enclosing environment at {}", i));
-            environment = Some(Rc::clone(&parent));
+            let parent = match &environment {
+                Some(env) => env.borrow().enclosing.clone(),
+                None => self.enclosing.clone(),
+            }
+            .unwrap_or_else(|| panic!("No enclosing environment at {}", i));
+            environment = Some(parent);
         }
         environment
     }
 }
+
+// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        Self::count(-1);
+    }
+}
@@ -1,16 +1,19 @@
 use crate::{
     ast::{expr, stmt, Expr, LiteralValue, Stmt},
     environment::Environment,
-    object::{Function, Object},
+    native,
+    object::{
+        format_number, ConversionError, Function, NativeFn, NumberDisplay,
+        Object,
+    },
+    shared::{Lock, NativeBody, Shared},
     token::{Token, TokenType},
 };
 use std::{
-    cell::RefCell,
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
     error::Error,
-    fmt,
-    rc::Rc,
-    result,
+    fmt, ptr, result,
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -18,6 +21,13 @@ use std::{
 pub enum InterpretError {
     TypeError { token: Token, message: String },
     UndefinedError { token: Token, message: String },
+    UseBeforeAssignment { token: Token, message: String },
+    AssertionFailed { token: Token, message: String },
+    TypeMismatch { token: Token, message: String },
+    AssignToConst { token: Token, message: String },
+    // A failed `Object` conversion in a native, raised with `?`. It has no
+    // token yet; the call pins it to its closing paren as a `TypeError`.
+    Conversion { error: ConversionError },
     Return { value: Object },
 }
 
@@ -34,6 +44,27 @@ impl fmt::Display for InterpretError {
                 "UndefinedError (line {} at {}) {}",
                 token.line, token.lexeme, message
             ),
+            Self::UseBeforeAssignment { token, message } => write!(
+                f,
+                "UseBeforeAssignment (line {} at {}) {}",
+                token.line, token.lexeme, message
+            ),
+            Self::AssertionFailed { token, message } => write!(
+                f,
+                "AssertionFailed (line {} at {}) {}",
+                token.line, token.lexeme, message
+            ),
+            Self::TypeMismatch { token, message } => write!(
+                f,
+                "TypeMismatch (line {} at {}) {}",
+                token.line, token.lexeme, message
+            ),
+            Self::AssignToConst { token, message } => write!(
+                f,
+                "AssignToConst (line {} at {}) {}",
+                token.line, token.lexeme, message
+            ),
+            Self::Conversion { error } => write!(f, "TypeError {}", error),
             Self::Return { value } => write!(f, "Return {:?}", value),
         }
     }
@@ -41,41 +72,217 @@ impl fmt::Display for InterpretError {
 
 impl Error for InterpretError {}
 
+impl From<ConversionError> for InterpretError {
+    fn from(error: ConversionError) -> Self {
+        Self::Conversion { error }
+    }
+}
+
+impl InterpretError {
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Self::TypeError { token, .. }
+            | Self::UndefinedError { token, .. }
+            | Self::UseBeforeAssignment { token, .. }
+            | Self::AssertionFailed { token, .. }
+            | Self::TypeMismatch { token, .. }
+            | Self::AssignToConst { token, .. } => Some(token),
+            Self::Conversion { .. } | Self::Return { .. } => None,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, InterpretError>;
 
+// Spreads the seed with a splitmix64 step, so nearby seeds start far
+// apart and none leaves xorshift stuck at zero.
+fn random_state(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    match z ^ (z >> 31) {
+        0 => 0x9e37_79b9_7f4a_7c15,
+        z => z,
+    }
+}
+
 pub struct Interpreter {
-    global: Rc<RefCell<Environment>>,
-    environment: Rc<RefCell<Environment>>,
+    global: Shared<Lock<Environment>>,
+    environment: Shared<Lock<Environment>>,
     locals: HashMap<Token, usize>,
+    declarations: HashMap<Token, Token>,
+    // Global names currently bound by `const`, recorded by the resolver so
+    // later REPL input cannot reassign them either.
+    constants: HashSet<String>,
+    // Block and call environments nothing else holds on to once they
+    // finish, kept for reuse by the next block or call.
+    free_environments: Vec<Shared<Lock<Environment>>>,
+    allocated_environments: usize,
+    // xorshift64* state behind `random` and `randomInt`; never zero.
+    random_state: u64,
+    pub check_uninitialized: bool,
+    // Checks a `var` initializer against the declaration's type annotation.
+    pub check_annotations: bool,
+    pub number_display: NumberDisplay,
+    // Runs `debug { ... }` blocks; with it off they are skipped entirely.
+    pub debug: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let global = Rc::new(RefCell::new(Environment::new()));
-        let clock = Object::Callable(Function::Native {
-            arity: 0,
-            body: Box::new(|_args: &Vec<Object>| {
-                Object::Number(
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Could not retrieve time.")
-                        .as_millis() as f64,
-                )
-            }),
-        });
-        global.borrow_mut().define("clock".to_string(), clock);
-        Interpreter {
-            environment: Rc::clone(&global),
+        let global = Shared::new(Lock::new(Environment::new()));
+        let mut interpreter = Interpreter {
+            environment: Shared::clone(&global),
             global,
             locals: HashMap::new(),
-        }
+            declarations: HashMap::new(),
+            constants: HashSet::new(),
+            free_environments: Vec::new(),
+            allocated_environments: 0,
+            random_state: random_state(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_nanos() as u64),
+            ),
+            check_uninitialized: false,
+            check_annotations: false,
+            number_display: NumberDisplay::Auto,
+            debug: true,
+        };
+        interpreter.define_native("clock", 0, native::clock);
+        interpreter.define_typed_native("sleep", &["number"], native::sleep);
+        interpreter.define_native("assertEq", 2, native::assert_eq);
+        interpreter.define_typed_native(
+            "approxEq",
+            &["number", "number", "number"],
+            native::approx_eq,
+        );
+        interpreter.define_variadic_native("min", native::min);
+        interpreter.define_variadic_native("max", native::max);
+        interpreter.define_native("contains", 2, native::contains);
+        interpreter.define_typed_native(
+            "format",
+            &["number", "number"],
+            native::format,
+        );
+        interpreter.define_native("repr", 1, native::repr);
+        interpreter.define_native("ord", 1, native::ord);
+        interpreter.define_native("chr", 1, native::chr);
+        interpreter.define_native("write", 1, native::write);
+        interpreter.define_native("eprint", 1, native::eprint);
+        interpreter.define_native("random", 0, native::random);
+        interpreter.define_typed_native(
+            "parseInt",
+            &["string", "number"],
+            native::parse_int,
+        );
+        interpreter.define_typed_native(
+            "parseFloat",
+            &["string"],
+            native::parse_float,
+        );
+        interpreter.define_typed_native(
+            "randomInt",
+            &["number", "number"],
+            native::random_int,
+        );
+        interpreter.define_typed_native("bytes", &["string"], native::bytes);
+        interpreter.define_typed_native(
+            "hexEncode",
+            &["bytes"],
+            native::hex_encode,
+        );
+        interpreter.define_typed_native(
+            "hexDecode",
+            &["string"],
+            native::hex_decode,
+        );
+        interpreter.define_typed_native(
+            "toString",
+            &["bytes"],
+            native::to_string,
+        );
+        interpreter.define_native("memoize", 1, native::memoize);
+        interpreter
+    }
+
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        body: impl NativeBody + 'static,
+    ) {
+        self.define_function(name, Some(arity), None, Shared::new(body));
+    }
+
+    pub fn define_typed_native(
+        &mut self,
+        name: &str,
+        signature: &'static [&'static str],
+        body: impl NativeBody + 'static,
+    ) {
+        let arity = Some(signature.len());
+        self.define_function(name, arity, Some(signature), Shared::new(body));
+    }
+
+    pub fn define_variadic_native(
+        &mut self,
+        name: &str,
+        body: impl NativeBody + 'static,
+    ) {
+        self.define_function(name, None, None, Shared::new(body));
     }
 
-    pub fn interpret(&mut self, statements: &Vec<Stmt>) -> Result<()> {
-        for stmt in statements {
-            self.execute(stmt)?;
+    fn define_function(
+        &mut self,
+        name: &str,
+        arity: Option<usize>,
+        signature: Option<&'static [&'static str]>,
+        body: NativeFn,
+    ) {
+        let function = Function::Native {
+            arity,
+            signature,
+            body,
+        };
+        self.global
+            .borrow_mut()
+            .define(name.to_string(), Object::Callable(function));
+    }
+
+    // Runs the program and yields the value of its final statement when
+    // that is an expression statement, or nil otherwise.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Object> {
+        // The first top-level function of each name is hoisted so it can
+        // be called before its declaration; everything else, including a
+        // later redefinition, runs in order. The resolver rejects a
+        // variable declared ahead of a hoisted function of the same name,
+        // whose value this would change.
+        let mut names = HashSet::new();
+        let (functions, rest): (Vec<&Stmt>, Vec<&Stmt>) =
+            statements.iter().partition(|stmt| match stmt {
+                Stmt::Function { name, .. } => names.insert(&name.lexeme),
+                _ => false,
+            });
+        let last = statements.last();
+        let mut value = Object::Nil;
+        for stmt in functions.into_iter().chain(rest) {
+            match stmt {
+                Stmt::Expression { expression }
+                    if last.is_some_and(|last| ptr::eq(stmt, last)) =>
+                {
+                    value = self.evaluate(expression)?;
+                }
+                _ => self.execute(stmt)?,
+            }
         }
-        Ok(())
+        Ok(value)
     }
 
     fn execute(&mut self, stmt: &Stmt) -> Result<()> {
@@ -84,19 +291,76 @@ impl Interpreter {
 
     pub fn execute_block(
         &mut self,
-        statements: &Vec<Stmt>,
-        env: Rc<RefCell<Environment>>,
+        statements: &[Stmt],
+        env: Shared<Lock<Environment>>,
     ) -> Result<()> {
-        let previous = Rc::clone(&self.environment);
+        let previous = Shared::clone(&self.environment);
         self.environment = env;
-        for stmt in statements {
-            self.execute(stmt)?;
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+        let env = std::mem::replace(&mut self.environment, previous);
+        // A closure created in the block keeps its own reference, so only
+        // environments nobody captured go back to the free-list.
+        if Shared::strong_count(&env) == 1 {
+            env.borrow_mut().reset(None);
+            self.free_environments.push(env);
+        }
+        result
+    }
+
+    pub fn new_environment(
+        &mut self,
+        enclosing: &Shared<Lock<Environment>>,
+    ) -> Shared<Lock<Environment>> {
+        match self.free_environments.pop() {
+            Some(env) => {
+                env.borrow_mut().reset(Some(enclosing));
+                env
+            }
+            None => {
+                self.allocated_environments += 1;
+                Shared::new(Lock::new(Environment::from(enclosing)))
+            }
+        }
+    }
+
+    pub fn allocated_environments(&self) -> usize {
+        self.allocated_environments
+    }
+
+    pub fn run_test(&mut self, body: &[Stmt]) -> Result<()> {
+        let environment = Shared::clone(&self.global);
+        let environment = self.new_environment(&environment);
+        self.execute_block(body, environment)
+    }
+
+    // Environments alive on this thread, across all interpreters. One that
+    // keeps growing after calls return points at a closure cycle.
+    #[cfg(debug_assertions)]
+    pub fn live_environments(&self) -> usize {
+        Environment::live()
+    }
+
+    // An interpreter whose `random` and `randomInt` produce the same
+    // sequence on every run.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            random_state: random_state(seed),
+            ..Self::new()
         }
-        self.environment = previous;
-        Ok(())
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Object> {
+    // The next number in [0, 1) from the interpreter's generator.
+    pub fn next_random(&mut self) -> f64 {
+        let mut x = self.random_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.random_state = x;
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64
+            / (1u64 << 53) as f64
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Object> {
         expr.accept(self)
     }
 
@@ -107,6 +371,160 @@ impl Interpreter {
         }
     }
 
+    fn binary(
+        &self,
+        operator: &Token,
+        left: Object,
+        right: Object,
+    ) -> Result<Object> {
+        match operator.r#type {
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                let ordering = match (&left, &right) {
+                    (Object::Number(ln), Object::Number(rn)) => {
+                        ln.partial_cmp(rn)
+                    }
+                    (Object::String(ls), Object::String(rs)) => {
+                        Some(ls.cmp(rs))
+                    }
+                    _ => {
+                        return Err(InterpretError::TypeError {
+                            token: operator.clone(),
+                            message: format!(
+                                "Cannot compare {} and {}.",
+                                left.type_name(),
+                                right.type_name()
+                            ),
+                        })
+                    }
+                };
+                let result = ordering.is_some_and(|o| match operator.r#type {
+                    TokenType::Greater => o == Ordering::Greater,
+                    TokenType::GreaterEqual => o != Ordering::Less,
+                    TokenType::Less => o == Ordering::Less,
+                    _ => o != Ordering::Greater,
+                });
+                Ok(Object::Boolean(result))
+            }
+            TokenType::Minus => match (&left, &right) {
+                (Object::Number(ln), Object::Number(rn)) => {
+                    Ok(Object::Number(ln - rn))
+                }
+                _ => Err(self.binary_operand_error(operator, &left, &right)),
+            },
+            TokenType::Slash => match (&left, &right) {
+                (Object::Number(ln), Object::Number(rn)) => {
+                    Ok(Object::Number(ln / rn))
+                }
+                _ => Err(self.binary_operand_error(operator, &left, &right)),
+            },
+            TokenType::Star => match (&left, &right) {
+                (Object::Number(ln), Object::Number(rn)) => {
+                    Ok(Object::Number(ln * rn))
+                }
+                _ => Err(self.binary_operand_error(operator, &left, &right)),
+            },
+            TokenType::Plus => match (&left, &right) {
+                (Object::Number(ln), Object::Number(rn)) => {
+                    Ok(Object::Number(ln + rn))
+                }
+                (Object::String(ls), Object::String(rs)) => {
+                    Ok(Object::String(format!("{}{}", ls, rs)))
+                }
+                _ => Err(self.binary_operand_error(operator, &left, &right)),
+            },
+            TokenType::BangEqual => Ok(Object::Boolean(!left.equals(&right))),
+            TokenType::EqualEqual => Ok(Object::Boolean(left.equals(&right))),
+            _ => unreachable!(),
+        }
+    }
+
+    // A nil operand is usually a variable that was never initialized, so
+    // it gets called out by side rather than reported as a type mismatch.
+    fn binary_operand_error(
+        &self,
+        operator: &Token,
+        left: &Object,
+        right: &Object,
+    ) -> InterpretError {
+        let message = match (left, right) {
+            (Object::Nil, _) => {
+                "Cannot use nil in arithmetic (left operand).".to_string()
+            }
+            (_, Object::Nil) => {
+                "Cannot use nil in arithmetic (right operand).".to_string()
+            }
+            _ if operator.r#type == TokenType::Plus => {
+                "Operands must be two numbers or two strings.".to_string()
+            }
+            _ => "Operand must be a number.".to_string(),
+        };
+        InterpretError::TypeError {
+            token: operator.clone(),
+            message,
+        }
+    }
+
+    fn has_type(&self, value: &Object, type_name: &Token) -> Result<bool> {
+        match (type_name.lexeme.as_str(), value) {
+            ("Number", Object::Number(_))
+            | ("String", Object::String(_))
+            | ("Boolean", Object::Boolean(_))
+            | ("Nil", Object::Nil)
+            | ("Function", Object::Callable(_)) => Ok(true),
+            ("Number", _)
+            | ("String", _)
+            | ("Boolean", _)
+            | ("Nil", _)
+            | ("Function", _) => Ok(false),
+            _ => Err(InterpretError::TypeError {
+                token: type_name.clone(),
+                message: format!("Unknown type '{}'.", type_name.lexeme),
+            }),
+        }
+    }
+
+    fn check_signature(
+        &self,
+        function: &Function,
+        paren: &Token,
+        args: &[Object],
+    ) -> Result<()> {
+        let signature = match function {
+            Function::Native {
+                signature: Some(signature),
+                ..
+            } => signature,
+            _ => return Ok(()),
+        };
+        for (position, (expected, arg)) in
+            signature.iter().zip(args).enumerate()
+        {
+            if arg.type_name() != *expected {
+                return Err(InterpretError::TypeError {
+                    token: paren.clone(),
+                    message: format!(
+                        "Expected a {} for argument {} but got a {}.",
+                        expected,
+                        position + 1,
+                        arg.type_name()
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Renders a value the way `print` shows it, honouring `number_display`.
+    pub fn stringify(&self, value: &Object) -> String {
+        match value {
+            Object::Number(n) => format_number(*n, self.number_display),
+            other => other.to_string(),
+        }
+    }
+
     fn is_truthy(&self, object: &Object) -> bool {
         match object {
             Object::Nil => false,
@@ -115,16 +533,68 @@ impl Interpreter {
         }
     }
 
-    pub fn resolve(&mut self, name: &Token, depth: usize) {
+    pub fn callable_globals(&self) -> HashSet<String> {
+        self.global
+            .borrow()
+            .values()
+            .filter(|(_, value)| matches!(value, Object::Callable(_)))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn native_globals(&self) -> HashSet<String> {
+        self.global
+            .borrow()
+            .values()
+            .filter(|(_, value)| {
+                matches!(value, Object::Callable(Function::Native { .. }))
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn resolve(&mut self, name: &Token, depth: usize, declaration: &Token) {
         self.locals.insert(name.clone(), depth);
+        self.declarations.insert(name.clone(), declaration.clone());
+    }
+
+    pub fn mark_constant(&mut self, name: &str, constant: bool) {
+        if constant {
+            self.constants.insert(name.to_string());
+        } else {
+            self.constants.remove(name);
+        }
+    }
+
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains(name)
+    }
+
+    // Maps each resolved local reference to the token that declared it.
+    // Globals are looked up dynamically and so are not included.
+    pub fn resolution_map(&self) -> &HashMap<Token, Token> {
+        &self.declarations
     }
 
     fn look_up_variable(&self, name: &Token) -> Result<Object> {
-        if let Some(distance) = self.locals.get(name) {
-            Ok(self.environment.borrow().get_at(*distance, name))
+        let value = if let Some(distance) = self.locals.get(name) {
+            self.environment.borrow().get_at(*distance, name)
         } else {
-            self.global.borrow().get(name)
+            // Unresolved names are globals, but the closest name may be a
+            // local in scope here.
+            let global = self.global.borrow().get(name);
+            global.map_err(|_| self.environment.borrow().undefined(name))?
+        };
+        if let Object::Uninitialized = value {
+            return Err(InterpretError::UseBeforeAssignment {
+                token: name.clone(),
+                message: format!(
+                    "Variable '{}' is used before being assigned.",
+                    name.lexeme
+                ),
+            });
         }
+        Ok(value)
     }
 }
 
@@ -138,75 +608,33 @@ impl expr::Visitor<Result<Object>> for Interpreter {
         }
     }
 
+    // Left-associative chains like `1 + 2 + 3 + ...` nest on the left, so
+    // the spine is walked in a loop rather than by recursion, which would
+    // overflow the stack on very long chains.
     fn visit_binary_expr(
         &mut self,
         left: &Expr,
-        operator: &crate::token::Token,
+        operator: &Token,
         right: &Expr,
     ) -> Result<Object> {
-        let left = self.evaluate(left)?;
-        let right = self.evaluate(right)?;
+        let mut spine = vec![(operator, right)];
+        let mut innermost = left;
+        while let Expr::Binary {
+            left,
+            operator,
+            right,
+        } = innermost
+        {
+            spine.push((operator, right));
+            innermost = left;
+        }
 
-        match operator.r#type {
-            TokenType::Greater => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Boolean(ln > rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::GreaterEqual => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Boolean(ln >= rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::Less => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Boolean(ln < rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::LessEqual => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Boolean(ln <= rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::Minus => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Number(ln - rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::Slash => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Number(ln / rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::Star => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Number(ln * rn))
-                }
-                _ => Err(self.number_operand_error(operator)),
-            },
-            TokenType::Plus => match (left, right) {
-                (Object::Number(ln), Object::Number(rn)) => {
-                    Ok(Object::Number(ln + rn))
-                }
-                (Object::String(ls), Object::String(rs)) => {
-                    Ok(Object::String(ls + &rs))
-                }
-                _ => Err(InterpretError::TypeError {
-                    token: operator.clone(),
-                    message: "Operands must be two numbers or two strings."
-                        .to_string(),
-                }),
-            },
-            TokenType::BangEqual => Ok(Object::Boolean(!left.equals(&right))),
-            TokenType::EqualEqual => Ok(Object::Boolean(left.equals(&right))),
-            _ => unreachable!(),
+        let mut value = self.evaluate(innermost)?;
+        for (operator, right) in spine.into_iter().rev() {
+            let right = self.evaluate(right)?;
+            value = self.binary(operator, value, right)?;
         }
+        Ok(value)
     }
 
     fn visit_grouping_expr(&mut self, expression: &Expr) -> Result<Object> {
@@ -245,12 +673,37 @@ impl expr::Visitor<Result<Object>> for Interpreter {
                 name,
                 value.clone(),
             );
+        } else if self.is_constant(&name.lexeme) {
+            // A function body can be resolved before a later top-level
+            // `const` marks the name, so globals are checked again here.
+            return Err(InterpretError::AssignToConst {
+                token: name.clone(),
+                message: format!(
+                    "Cannot assign to constant '{}'.",
+                    name.lexeme
+                ),
+            });
         } else {
             self.environment.borrow_mut().assign(name, value.clone())?;
         }
         Ok(value)
     }
 
+    fn visit_default_assign_expr(
+        &mut self,
+        name: &Token,
+        value: &Expr,
+    ) -> Result<Object> {
+        let current = match self.look_up_variable(name) {
+            Err(InterpretError::UseBeforeAssignment { .. }) => Object::Nil,
+            current => current?,
+        };
+        match current {
+            Object::Nil => self.visit_assign_expr(name, value),
+            current => Ok(current),
+        }
+    }
+
     fn visit_logical_expr(
         &mut self,
         left: &Expr,
@@ -258,9 +711,11 @@ impl expr::Visitor<Result<Object>> for Interpreter {
         right: &Expr,
     ) -> Result<Object> {
         let left = self.evaluate(left)?;
-        if operator.r#type == TokenType::Or && self.is_truthy(&left) {
-            Ok(left)
-        } else if operator.r#type == TokenType::And && !self.is_truthy(&left) {
+        let short_circuit = match operator.r#type {
+            TokenType::Or => self.is_truthy(&left),
+            _ => !self.is_truthy(&left),
+        };
+        if short_circuit {
             Ok(left)
         } else {
             self.evaluate(right)
@@ -271,27 +726,28 @@ impl expr::Visitor<Result<Object>> for Interpreter {
         &mut self,
         callee: &Expr,
         paren: &Token,
-        arguments: &Vec<Expr>,
+        arguments: &[Expr],
     ) -> Result<Object> {
         let callee_value = self.evaluate(callee)?;
-        let argument_values: Result<Vec<Object>> = arguments
-            .into_iter()
-            .map(|expr| self.evaluate(expr))
-            .collect();
+        let argument_values: Result<Vec<Object>> =
+            arguments.iter().map(|expr| self.evaluate(expr)).collect();
         let args = argument_values?;
         if let Object::Callable(function) = callee_value {
             let size = args.len();
-            if size != function.arity() {
-                Err(InterpretError::TypeError {
-                    token: paren.clone(),
-                    message: format!(
-                        "Expected {} arguments but got {}.",
-                        function.arity(),
-                        size
-                    ),
-                })
-            } else {
-                function.call(self, &args)
+            match function.arity() {
+                Some(arity) if arity != size => {
+                    Err(InterpretError::TypeError {
+                        token: paren.clone(),
+                        message: format!(
+                            "Expected {} arguments but got {}.",
+                            arity, size
+                        ),
+                    })
+                }
+                _ => {
+                    self.check_signature(&function, paren, &args)?;
+                    function.call(self, paren, &args)
+                }
             }
         } else {
             Err(InterpretError::TypeError {
@@ -300,14 +756,65 @@ impl expr::Visitor<Result<Object>> for Interpreter {
             })
         }
     }
+
+    fn visit_is_expr(
+        &mut self,
+        value: &Expr,
+        type_name: &Token,
+    ) -> Result<Object> {
+        let value = self.evaluate(value)?;
+        Ok(Object::Boolean(self.has_type(&value, type_name)?))
+    }
+
+    fn visit_interpolation_expr(&mut self, parts: &[Expr]) -> Result<Object> {
+        let mut s = String::new();
+        for part in parts {
+            let value = self.evaluate(part)?;
+            s.push_str(&self.stringify(&value));
+        }
+        Ok(Object::String(s))
+    }
+
+    fn visit_when_expr(
+        &mut self,
+        arms: &[(Expr, Expr)],
+        else_arm: Option<&Expr>,
+    ) -> Result<Object> {
+        for (condition, value) in arms {
+            let condition = self.evaluate(condition)?;
+            if self.is_truthy(&condition) {
+                return self.evaluate(value);
+            }
+        }
+        match else_arm {
+            Some(else_arm) => self.evaluate(else_arm),
+            None => Ok(Object::Nil),
+        }
+    }
 }
 
 impl stmt::Visitor<Result<()>> for Interpreter {
-    fn visit_block_stmt(&mut self, statements: &Vec<Stmt>) -> Result<()> {
-        self.execute_block(
-            statements,
-            Rc::new(RefCell::new(Environment::from(&self.environment))),
-        )
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
+        let environment = Shared::clone(&self.environment);
+        let environment = self.new_environment(&environment);
+        self.execute_block(statements, environment)
+    }
+
+    // Tests only run when asked for, through `run_test`.
+    fn visit_test_stmt(&mut self, _name: &Token, _body: &[Stmt]) -> Result<()> {
+        Ok(())
+    }
+
+    fn visit_nil_stmt(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn visit_debug_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
+        if self.debug {
+            self.visit_block_stmt(statements)
+        } else {
+            Ok(())
+        }
     }
 
     fn visit_expression_stmt(&mut self, expression: &Expr) -> Result<()> {
@@ -317,19 +824,52 @@ impl stmt::Visitor<Result<()>> for Interpreter {
 
     fn visit_print_stmt(&mut self, expression: &Expr) -> Result<()> {
         let value = self.evaluate(expression)?;
-        println!("{}", value);
+        println!("{}", self.stringify(&value));
         Ok(())
     }
 
     fn visit_var_stmt(
         &mut self,
         name: &Token,
+        annotation: Option<&Token>,
         initializer: &Option<Expr>,
     ) -> Result<()> {
+        let uninitialized = if self.check_uninitialized {
+            Object::Uninitialized
+        } else {
+            Object::Nil
+        };
         let value = initializer
             .as_ref()
-            .map(|v| self.evaluate(&v))
-            .unwrap_or(Ok(Object::Nil))?;
+            .map(|v| self.evaluate(v))
+            .unwrap_or(Ok(uninitialized))?;
+        let checked = annotation
+            .filter(|_| self.check_annotations && initializer.is_some());
+        if let Some(annotation) = checked {
+            if !self.has_type(&value, annotation)? {
+                return Err(InterpretError::TypeMismatch {
+                    token: name.clone(),
+                    message: format!(
+                        "'{}' is declared as {} but was given a {}.",
+                        name.lexeme,
+                        annotation.lexeme,
+                        value.type_name()
+                    ),
+                });
+            }
+        }
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme.clone(), value);
+        Ok(())
+    }
+
+    fn visit_const_stmt(
+        &mut self,
+        name: &Token,
+        initializer: &Expr,
+    ) -> Result<()> {
+        let value = self.evaluate(initializer)?;
         self.environment
             .borrow_mut()
             .define(name.lexeme.clone(), value);
@@ -344,7 +884,7 @@ impl stmt::Visitor<Result<()>> for Interpreter {
     ) -> Result<()> {
         let condition_value = self.evaluate(condition)?;
         if self.is_truthy(&condition_value) {
-            self.execute(&then_branch)?;
+            self.execute(then_branch)?;
         } else if let Some(else_branch) = else_branch {
             self.execute(else_branch)?;
         }
@@ -353,13 +893,16 @@ impl stmt::Visitor<Result<()>> for Interpreter {
 
     fn visit_while_stmt(
         &mut self,
+        _keyword: &Token,
         condition: &Expr,
         body: &Stmt,
     ) -> Result<()> {
-        let mut value = self.evaluate(condition)?;
-        while self.is_truthy(&value) {
+        loop {
+            let value = self.evaluate(condition)?;
+            if !self.is_truthy(&value) {
+                break;
+            }
             self.execute(body)?;
-            value = self.evaluate(condition)?;
         }
         Ok(())
     }
@@ -367,14 +910,14 @@ impl stmt::Visitor<Result<()>> for Interpreter {
     fn visit_function_stmt(
         &mut self,
         name: &Token,
-        params: &Vec<Token>,
-        body: &Vec<Stmt>,
+        params: &Shared<[Token]>,
+        body: &Shared<[Stmt]>,
     ) -> Result<()> {
         let function = Function::User {
-            name: name.clone(),
-            params: params.clone(),
-            body: body.clone(),
-            closure: Rc::clone(&self.environment),
+            name: Shared::new(name.clone()),
+            params: Shared::clone(params),
+            body: Shared::clone(body),
+            closure: Shared::clone(&self.environment),
         };
         self.environment
             .borrow_mut()
@@ -382,6 +925,23 @@ impl stmt::Visitor<Result<()>> for Interpreter {
         Ok(())
     }
 
+    fn visit_assert_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: &Expr,
+        text: &str,
+    ) -> Result<()> {
+        let value = self.evaluate(condition)?;
+        if self.is_truthy(&value) {
+            Ok(())
+        } else {
+            Err(InterpretError::AssertionFailed {
+                token: keyword.clone(),
+                message: format!("assertion failed: {}", text),
+            })
+        }
+    }
+
     fn visit_return_stmt(
         &mut self,
         _keyword: &Token,
@@ -396,3 +956,936 @@ impl stmt::Visitor<Result<()>> for Interpreter {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, resolver::Resolver};
+
+    fn interpret(
+        interpreter: &mut Interpreter,
+        source: &str,
+    ) -> Result<Object> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan().unwrap();
+        let statements = Parser::with_source(tokens, source).parse().unwrap();
+        Resolver::new(interpreter)
+            .resolve_stmts(&statements)
+            .unwrap();
+        interpreter.interpret(&statements)
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Object {
+        let token = Token::new(TokenType::Identifier, name, 0, 0);
+        interpreter.global.borrow().get(&token).unwrap()
+    }
+
+    #[test]
+    fn test_while_condition_evaluated_once_per_iteration() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var calls = 0;
+            var iterations = 0;
+            fun condition() {
+                calls = calls + 1;
+                return calls <= 3;
+            }
+            while (condition()) iterations = iterations + 1;
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "iterations").equals(&Object::Number(3.0)));
+        assert!(global(&interpreter, "calls").equals(&Object::Number(4.0)));
+    }
+
+    #[test]
+    fn test_assign_undefined_reports_name_line() {
+        let mut interpreter = Interpreter::new();
+        let source = "var y = 1;\nx =\n  y;";
+        match interpret(&mut interpreter, source) {
+            Err(InterpretError::UndefinedError { token, .. }) => {
+                assert_eq!(token.lexeme, "x");
+                assert_eq!(token.line, 2);
+            }
+            _ => panic!("Expected an UndefinedError"),
+        }
+    }
+
+    #[test]
+    fn test_closures_from_factory_are_independent() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var a = makeCounter();
+            var b = makeCounter();
+            a();
+            a();
+            var first = a();
+            var second = b();
+            var alias = a;
+            alias();
+            var shared = a();
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "first").equals(&Object::Number(3.0)));
+        assert!(global(&interpreter, "second").equals(&Object::Number(1.0)));
+        assert!(global(&interpreter, "shared").equals(&Object::Number(5.0)));
+    }
+
+    #[test]
+    fn test_negative_zero_product() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var z = 0 * -1;").unwrap();
+        assert_eq!(global(&interpreter, "z").to_string(), "-0");
+    }
+
+    #[test]
+    fn test_chained_assignment() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var a; var b; a = b = 5;").unwrap();
+        assert!(global(&interpreter, "a").equals(&Object::Number(5.0)));
+        assert!(global(&interpreter, "b").equals(&Object::Number(5.0)));
+    }
+
+    #[test]
+    fn test_chained_assignment_in_closure() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun outer() {
+                var a;
+                var b;
+                fun inner() {
+                    a = b = 7;
+                }
+                inner();
+                return a + b;
+            }
+            var result = outer();
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "result").equals(&Object::Number(14.0)));
+    }
+
+    #[test]
+    fn test_assign_to_shadowing_local() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var inner;
+            var outer;
+            {
+                var a = 1;
+                {
+                    var a = 2;
+                    a = 3;
+                    inner = a;
+                }
+                outer = a;
+            }
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "inner").equals(&Object::Number(3.0)));
+        assert!(global(&interpreter, "outer").equals(&Object::Number(1.0)));
+    }
+
+    #[test]
+    fn test_is_builtin_type() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var isNumber = 1 is Number;
+            var isString = "1" is Number;
+            var isNil = nil is Nil;
+            var isFunction = clock is Function;
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "isNumber").equals(&Object::Boolean(true)));
+        assert!(
+            global(&interpreter, "isString").equals(&Object::Boolean(false))
+        );
+        assert!(global(&interpreter, "isNil").equals(&Object::Boolean(true)));
+        assert!(
+            global(&interpreter, "isFunction").equals(&Object::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_is_unknown_type() {
+        let mut interpreter = Interpreter::new();
+        assert!(matches!(
+            interpret(&mut interpreter, "print 1 is Animal;"),
+            Err(InterpretError::TypeError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_uninitialized_var_reads_nil_by_default() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var x; var y = x;").unwrap();
+        assert!(global(&interpreter, "y").equals(&Object::Nil));
+    }
+
+    #[test]
+    fn test_uninitialized_var_check() {
+        let mut interpreter = Interpreter::new();
+        interpreter.check_uninitialized = true;
+        assert!(matches!(
+            interpret(&mut interpreter, "var x; var y = x;"),
+            Err(InterpretError::UseBeforeAssignment { .. })
+        ));
+        interpret(&mut interpreter, "{ var z; z = 1; print z; }").unwrap();
+    }
+
+    #[test]
+    fn test_sleep_stub() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("sleep", 1, |_, _, _| Ok(Object::Nil));
+        let start = std::time::Instant::now();
+        interpret(&mut interpreter, "var r = sleep(100);").unwrap();
+        assert!(start.elapsed().as_millis() < 100);
+        assert!(global(&interpreter, "r").equals(&Object::Nil));
+    }
+
+    #[test]
+    fn test_sleep_validates_argument() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var r = sleep(0);").unwrap();
+        assert!(global(&interpreter, "r").equals(&Object::Nil));
+        assert!(interpret(&mut interpreter, "sleep(-1);").is_err());
+        assert!(interpret(&mut interpreter, "sleep(\"1\");").is_err());
+    }
+
+    #[test]
+    fn test_compare_strings() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var less = "apple" < "banana";
+            var greater = "b" >= "a";
+            var nan = (0 / 0) < 1;
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "less").equals(&Object::Boolean(true)));
+        assert!(global(&interpreter, "greater").equals(&Object::Boolean(true)));
+        assert!(global(&interpreter, "nan").equals(&Object::Boolean(false)));
+    }
+
+    #[test]
+    fn test_compare_mismatched_types() {
+        let mut interpreter = Interpreter::new();
+        match interpret(&mut interpreter, "print 1 < \"a\";") {
+            Err(InterpretError::TypeError { message, .. }) => {
+                assert_eq!(message, "Cannot compare number and string.")
+            }
+            _ => panic!("Expected a TypeError"),
+        }
+        match interpret(&mut interpreter, "print true > nil;") {
+            Err(InterpretError::TypeError { message, .. }) => {
+                assert_eq!(message, "Cannot compare boolean and nil.")
+            }
+            _ => panic!("Expected a TypeError"),
+        }
+    }
+
+    #[test]
+    fn test_assert_eq() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(1 + 1, 2);
+            assertEq("a" + "b", "ab");
+            assertEq(nil, nil);
+            assertEq(true, !false);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        match interpret(&mut interpreter, "assertEq(1 + 2, 4);") {
+            Err(InterpretError::AssertionFailed { message, .. }) => {
+                assert_eq!(message, "expected 4 but got 3")
+            }
+            _ => panic!("Expected an AssertionFailed"),
+        }
+    }
+
+    #[test]
+    fn test_block_scoped_functions() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun f() { return "outer"; }
+            var inner;
+            var factorial;
+            {
+                fun f() { return "inner"; }
+                inner = f();
+                fun fact(n) {
+                    if (n <= 1) return 1;
+                    return n * fact(n - 1);
+                }
+                factorial = fact(5);
+            }
+            var outer = f();
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        let inner = Object::String("inner".to_string());
+        assert!(global(&interpreter, "inner").equals(&inner));
+        let outer = Object::String("outer".to_string());
+        assert!(global(&interpreter, "outer").equals(&outer));
+        let factorial = Object::Number(120.0);
+        assert!(global(&interpreter, "factorial").equals(&factorial));
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(max(1, 5, 3), 5);
+            assertEq(min(2, -1), -1);
+            assertEq(max(7), 7);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "max();").is_err());
+        assert!(interpret(&mut interpreter, "min(1, \"2\");").is_err());
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var x = 1;
+            assertEq("x = ${x + 1}", "x = 2");
+            assertEq("${x}${x}", "11");
+            assertEq("a ${"b ${x * 3} c"} d", "a b 3 c d");
+            assertEq("${max(x, 4)} and ${x is Number}", "4 and true");
+            assertEq("${"a" + "${x}"}b${"c"}", "a1bc");
+            var escaped = "\${x}";
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        let escaped = Object::String("${x}".to_string());
+        assert!(global(&interpreter, "escaped").equals(&escaped));
+    }
+
+    #[test]
+    fn test_environments_are_reused() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun add(a, b) { return a + b; }
+            var sum = 0;
+            for (var i = 0; i < 1000; i = i + 1) {
+                var step = i;
+                sum = add(sum, step);
+            }
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "sum").equals(&Object::Number(499500.0)));
+        assert!(interpreter.allocated_environments() < 10);
+    }
+
+    #[test]
+    fn test_reused_environments_keep_closures() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun counter(start) {
+                var count = start;
+                fun next() { count = count + 1; return count; }
+                return next;
+            }
+            var first = counter(0);
+            var second = counter(10);
+            { var unrelated = 1; }
+            first();
+            var a = first();
+            var b = second();
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "a").equals(&Object::Number(2.0)));
+        assert!(global(&interpreter, "b").equals(&Object::Number(11.0)));
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(contains("interpreter", "pre"), true);
+            assertEq(contains("interpreter", "post"), false);
+            assertEq(contains("lox", ""), true);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "contains(12, \"1\");").is_err());
+        assert!(interpret(&mut interpreter, "contains(\"1\", 1);").is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(format(3.14159, 2), "3.14");
+            assertEq(format(9.99, 1), "10.0");
+            assertEq(format(42.7, 0), "43");
+            assertEq(format(2, 3), "2.000");
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "format(\"1\", 2);").is_err());
+        assert!(interpret(&mut interpreter, "format(1, -1);").is_err());
+        assert!(interpret(&mut interpreter, "format(1, 1.5);").is_err());
+    }
+
+    #[test]
+    fn test_function_identity() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun f() {}
+            fun g() {}
+            fun make() { fun h() {} return h; }
+            var alias = f;
+            assertEq(alias == f, true);
+            assertEq(f == g, false);
+            assertEq(clock == clock, true);
+            assertEq(clock == sleep, false);
+            assertEq(make() == make(), false);
+            var h = make();
+            assertEq(h == h, true);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+    }
+
+    #[test]
+    fn test_const_read() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            const PI = 3.14;
+            fun area(r) { const SQUARE = r * r; return PI * SQUARE; }
+            var a = area(2);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "a").equals(&Object::Number(12.56)));
+    }
+
+    #[test]
+    fn test_hoisted_function_cannot_assign_const() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun reset() { LIMIT = 0; }
+            const LIMIT = 10;
+            reset();
+        "#;
+        assert!(matches!(
+            interpret(&mut interpreter, source),
+            Err(InterpretError::AssignToConst { token, .. }) if token.line == 2
+        ));
+        assert!(global(&interpreter, "LIMIT").equals(&Object::Number(10.0)));
+    }
+
+    #[test]
+    fn test_when_expression() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun sign(n) {
+                return when { n < 0: "negative", n == 0: "zero", else: "positive" };
+            }
+            assertEq(sign(-3), "negative");
+            assertEq(sign(0), "zero");
+            assertEq(sign(9), "positive");
+            assertEq(when { true: 1, true: 2 }, 1);
+            assertEq(when { false: 1 }, nil);
+            assertEq(when {}, nil);
+            var calls = 0;
+            fun tick() { calls = calls + 1; return true; }
+            when { tick(): 1, tick(): 2 };
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "calls").equals(&Object::Number(1.0)));
+    }
+
+    #[test]
+    fn test_function_definitions_share_body() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var first;
+            var last;
+            for (var i = 0; i < 100; i = i + 1) {
+                fun f() { return i; }
+                if (first == nil) first = f;
+                last = f;
+            }
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        let body = |name: &str| match global(&interpreter, name) {
+            Object::Callable(Function::User { body, .. }) => body,
+            _ => panic!("Expected a user function"),
+        };
+        assert!(Shared::ptr_eq(&body("first"), &body("last")));
+        assert!(!global(&interpreter, "first")
+            .equals(&global(&interpreter, "last")));
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(0.1 + 0.2 == 0.3, false);
+            assertEq(approxEq(0.1 + 0.2, 0.3, 0.000000001), true);
+            assertEq(approxEq(1, 1.5, 0.1), false);
+            assertEq(approxEq(2, 2, 0), true);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "approxEq(1, 1, -1);").is_err());
+        assert!(interpret(&mut interpreter, "approxEq(1, nil, 1);").is_err());
+    }
+
+    #[test]
+    fn test_nil_arithmetic_message() {
+        let mut interpreter = Interpreter::new();
+        let message = |interpreter: &mut Interpreter, source: &str| {
+            interpret(interpreter, source).unwrap_err().to_string()
+        };
+        assert!(message(&mut interpreter, "var a; a + 1;")
+            .ends_with("Cannot use nil in arithmetic (left operand)."));
+        assert!(message(&mut interpreter, "1 * nil;")
+            .ends_with("Cannot use nil in arithmetic (right operand)."));
+        assert!(message(&mut interpreter, "1 - \"a\";")
+            .ends_with("Operand must be a number."));
+        assert!(message(&mut interpreter, "true + 1;")
+            .ends_with("Operands must be two numbers or two strings."));
+    }
+
+    #[test]
+    fn test_repr() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var quoted = repr("say \"hi\"\n\tand \${x} \\ \e");
+            assertEq(repr(1.5), "1.5");
+            assertEq(repr(true), "true");
+            assertEq(repr(nil), "nil");
+            assertEq(repr(""), "\"\"");
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        let expected = r#""say \"hi\"\n\tand \${x} \\ \e""#;
+        let quoted = Object::String(expected.to_string());
+        assert!(global(&interpreter, "quoted").equals(&quoted));
+
+        let source = format!("var roundtrip = {};", expected);
+        interpret(&mut interpreter, &source).unwrap();
+        let roundtrip = Object::String("say \"hi\"\n\tand ${x} \\ \x1b".into());
+        assert!(global(&interpreter, "roundtrip").equals(&roundtrip));
+    }
+
+    #[test]
+    fn test_return_from_nested_block_restores_environment() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            fun find(limit) {
+                var i = 0;
+                while (true) {
+                    {
+                        var shadow = "callee";
+                        if (i == limit) { return i; }
+                    }
+                    i = i + 1;
+                }
+            }
+            var result;
+            {
+                var shadow = "caller";
+                result = find(3);
+                assertEq(shadow, "caller");
+                shadow = "updated";
+                assertEq(shadow, "updated");
+            }
+            var after = result + 1;
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "after").equals(&Object::Number(4.0)));
+        let error = interpret(&mut interpreter, "{ var x = 1; { x + nil; } }");
+        assert!(error.is_err());
+        assert!(Shared::ptr_eq(
+            &interpreter.environment,
+            &interpreter.global
+        ));
+    }
+
+    #[test]
+    fn test_deep_binary_chain() {
+        let mut interpreter = Interpreter::new();
+        let chain = vec!["1"; 5000].join(" + ");
+        let source =
+            format!("var sum = {}; var mixed = 10 - 2 * 3 - 1;", chain);
+        interpret(&mut interpreter, &source).unwrap();
+        assert!(global(&interpreter, "sum").equals(&Object::Number(5000.0)));
+        assert!(global(&interpreter, "mixed").equals(&Object::Number(3.0)));
+        let error =
+            interpret(&mut interpreter, "1 + 2 + nil + 3;").unwrap_err();
+        assert!(error.to_string().ends_with("(right operand)."));
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var n: Number = 5;
+            var s: String = "five";
+            var later: Number;
+            var wrong: String = 5;
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+
+        interpreter.check_annotations = true;
+        interpret(&mut interpreter, "var m: Number = 1 + 2;").unwrap();
+        interpret(&mut interpreter, "var unset: Number;").unwrap();
+        let error = interpret(&mut interpreter, "var t: String = 5;");
+        assert!(matches!(
+            error,
+            Err(InterpretError::TypeMismatch { token, message })
+                if token.lexeme == "t" && message.contains("given a number")
+        ));
+        assert!(interpret(&mut interpreter, "var u: Widget = 5;").is_err());
+    }
+
+    #[test]
+    fn test_ord_chr() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(ord("A"), 65);
+            assertEq(ord("ab"), 97);
+            assertEq(ord("é"), 233);
+            assertEq(chr(65), "A");
+            assertEq(chr(ord("z")), "z");
+            assertEq(chr(128512), "😀");
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "ord(\"\");").is_err());
+        let error = interpret(&mut interpreter, "ord(65);").unwrap_err();
+        assert!(matches!(
+            &error,
+            InterpretError::TypeError { token, message }
+                if token.lexeme == ")"
+                    && message == "Expected a string but got a number."
+        ));
+        assert!(interpret(&mut interpreter, "chr(55296);").is_err());
+        assert!(interpret(&mut interpreter, "chr(1114112);").is_err());
+        assert!(interpret(&mut interpreter, "chr(65.5);").is_err());
+        assert!(interpret(&mut interpreter, "chr(-1);").is_err());
+        let error = interpret(&mut interpreter, "chr(\"A\");").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "TypeError (line 1 at )) Expected a number but got a string."
+        );
+    }
+
+    #[test]
+    fn test_interpret_returns_last_value() {
+        let mut interpreter = Interpreter::new();
+        let value = interpret(&mut interpreter, "1; 2; 3;").unwrap();
+        assert!(value.equals(&Object::Number(3.0)));
+        let value = interpret(&mut interpreter, "4; var x = 1;").unwrap();
+        assert!(value.equals(&Object::Nil));
+        let value = interpret(&mut interpreter, "5; fun f() {}").unwrap();
+        assert!(value.equals(&Object::Nil));
+    }
+
+    #[test]
+    fn test_native_signature() {
+        let mut interpreter = Interpreter::new();
+        let error =
+            interpret(&mut interpreter, "format(1, \"2\");").unwrap_err();
+        assert!(matches!(
+            error,
+            InterpretError::TypeError { message, .. }
+                if message == "Expected a number for argument 2 but got a string."
+        ));
+        let error = interpret(&mut interpreter, "sleep(nil);").unwrap_err();
+        assert!(error.to_string().contains("argument 1 but got a nil"));
+    }
+
+    #[test]
+    fn test_number_display_option() {
+        let mut interpreter = Interpreter::new();
+        interpreter.number_display = NumberDisplay::Scientific;
+        interpret(
+            &mut interpreter,
+            "var s = \"${100000000000000000000} ${1 / 10000}\";",
+        )
+        .unwrap();
+        let expected = Object::String("1e20 1e-4".to_string());
+        assert!(global(&interpreter, "s").equals(&expected));
+    }
+
+    #[test]
+    fn test_loop_bindings() {
+        let source = "
+            var first; var second; var last;
+            for (var i = 0; i < 3; i = i + 1) {
+                fun get() { return i; }
+                if (i == 0) first = get;
+                if (i == 1) { second = get; i = i + 1; }
+            }
+            last = first() + second();
+        ";
+        let mut interpreter = Interpreter::new();
+        let tokens = Lexer::new(source).scan().unwrap().to_vec();
+        let statements =
+            Parser::new(&tokens).loop_bindings(true).parse().unwrap();
+        Resolver::new(&mut interpreter)
+            .resolve_stmts(&statements)
+            .unwrap();
+        interpreter.interpret(&statements).unwrap();
+        assert!(global(&interpreter, "last").equals(&Object::Number(2.0)));
+
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "last").equals(&Object::Number(6.0)));
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let source = "
+            var a = random(); var b = random();
+            var i = randomInt(1, 6); var j = randomInt(-2, 2);
+        ";
+        let mut first = Interpreter::with_seed(42);
+        interpret(&mut first, source).unwrap();
+        let mut second = Interpreter::with_seed(42);
+        interpret(&mut second, source).unwrap();
+        for name in ["a", "b", "i", "j"] {
+            assert!(global(&first, name).equals(&global(&second, name)));
+        }
+        match (global(&first, "a"), global(&first, "b")) {
+            (Object::Number(a), Object::Number(b)) => {
+                assert!((0.0..1.0).contains(&a) && (0.0..1.0).contains(&b));
+                assert_ne!(a, b);
+            }
+            other => panic!("{:?}", other),
+        }
+        match (global(&first, "i"), global(&first, "j")) {
+            (Object::Number(i), Object::Number(j)) => {
+                assert!((1.0..=6.0).contains(&i) && i.fract() == 0.0);
+                assert!((-2.0..=2.0).contains(&j) && j.fract() == 0.0);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let mut interpreter = Interpreter::with_seed(42);
+        interpret(
+            &mut interpreter,
+            "assertEq(randomInt(1, 100), 20); assertEq(randomInt(1, 100), 57);
+             assertEq(randomInt(1, 100), 49); assertEq(randomInt(1, 100), 28);",
+        )
+        .unwrap();
+        assert!(interpret(&mut interpreter, "randomInt(3, 1);").is_err());
+        assert!(interpret(&mut interpreter, "randomInt(1, 2.5);").is_err());
+    }
+
+    #[test]
+    fn test_default_assign() {
+        let mut interpreter = Interpreter::new();
+        interpreter.check_uninitialized = true;
+        interpret(
+            &mut interpreter,
+            "
+            var calls = 0;
+            fun five() { calls = calls + 1; return 5; }
+            var x; x ??= five();
+            var y = 1; var z = (y ??= five());
+            x ??= five();
+            ",
+        )
+        .unwrap();
+        assert!(global(&interpreter, "x").equals(&Object::Number(5.0)));
+        assert!(global(&interpreter, "y").equals(&Object::Number(1.0)));
+        assert!(global(&interpreter, "z").equals(&Object::Number(1.0)));
+        assert!(global(&interpreter, "calls").equals(&Object::Number(1.0)));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_live_environments_show_cycles() {
+        let mut interpreter = Interpreter::new();
+        interpret(
+            &mut interpreter,
+            "
+            fun plain() { var f = 1; { var g = f; } }
+            fun cycle() { var f; fun g() { return f; } f = g; }
+            plain();
+            ",
+        )
+        .unwrap();
+        let before = interpreter.live_environments();
+        interpret(&mut interpreter, "plain(); plain();").unwrap();
+        assert_eq!(interpreter.live_environments(), before);
+        // Each call leaks its environment; the first few are taken from the
+        // pool, so they don't add to the count.
+        let calls = "cycle();".repeat(10);
+        interpret(&mut interpreter, &calls).unwrap();
+        assert!(interpreter.live_environments() > before + 5);
+    }
+
+    #[test]
+    fn test_assert_shows_source() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var x = 3; assert(x < 5);").unwrap();
+        let error = interpret(
+            &mut interpreter,
+            "assert(x  >  5 and contains(\"abc\", \"d\"));",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            InterpretError::AssertionFailed { token, message }
+                if token.lexeme == "assert"
+                    && message == "assertion failed: x  >  5 and contains(\"abc\", \"d\")"
+        ));
+        let error =
+            interpret(&mut interpreter, "assert(\"${1}\\t\" == \"2\");")
+                .unwrap_err();
+        assert!(matches!(
+            error,
+            InterpretError::AssertionFailed { message, .. }
+                if message == "assertion failed: \"${1}\\t\" == \"2\""
+        ));
+    }
+
+    #[test]
+    fn test_debug_blocks() {
+        let source =
+            "var checks = 0; debug { var n = 1; checks = checks + n; }";
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "checks").equals(&Object::Number(1.0)));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.debug = false;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "checks").equals(&Object::Number(0.0)));
+        interpret(&mut interpreter, "debug { assert(false); }").unwrap();
+    }
+
+    #[test]
+    fn test_undefined_suggests_close_name() {
+        let mut interpreter = Interpreter::new();
+        interpret(&mut interpreter, "var count = 1; var counter = 2;").unwrap();
+        let message = |interpreter: &mut Interpreter, source: &str| {
+            interpret(interpreter, source).unwrap_err().to_string()
+        };
+        let error = message(&mut interpreter, "print cont;");
+        assert!(error.ends_with("Did you mean 'count'?"), "{}", error);
+        let error = message(&mut interpreter, "conter = 3;");
+        assert!(error.ends_with("Did you mean 'counter'?"), "{}", error);
+        let error = message(&mut interpreter, "clok();");
+        assert!(error.ends_with("Did you mean 'clock'?"), "{}", error);
+        let error = message(&mut interpreter, "print xyz;");
+        assert!(error.ends_with("Undefined variable 'xyz'."), "{}", error);
+
+        let source = "fun g() { var count = 1; print cont; } g();";
+        let error = message(&mut Interpreter::new(), source);
+        assert!(error.ends_with("Did you mean 'count'?"), "{}", error);
+        let source = "fun h() { var total = 1; { totl = 2; } } h();";
+        let error = message(&mut Interpreter::new(), source);
+        assert!(error.ends_with("Did you mean 'total'?"), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            assertEq(parseInt("FF", 16), 255);
+            assertEq(parseInt(" -101 ", 2), -5);
+            assertEq(parseInt("z", 36), 35);
+            assertEq(parseInt("xyz", 10), nil);
+            assertEq(parseInt("12.5", 10), nil);
+            assertEq(parseInt("", 10), nil);
+            assertEq(parseFloat("3.25"), 3.25);
+            assertEq(parseFloat("-0.5"), -0.5);
+            assertEq(parseFloat("abc"), nil);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(interpret(&mut interpreter, "parseInt(\"1\", 37);").is_err());
+        assert!(interpret(&mut interpreter, "parseInt(\"1\", 2.5);").is_err());
+        assert!(interpret(&mut interpreter, "parseInt(1, 10);").is_err());
+    }
+
+    #[test]
+    fn test_recovered_statement_is_skipped() {
+        let tokens = Lexer::new("var a = 1;\nprint a +;\nvar b = a + 1;")
+            .scan()
+            .unwrap()
+            .to_vec();
+        let (statements, errors) = Parser::new(&tokens).parse_recovering();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(statements[1], Stmt::Nil));
+        let mut interpreter = Interpreter::new();
+        Resolver::new(&mut interpreter)
+            .resolve_stmts(&statements)
+            .unwrap();
+        interpreter.interpret(&statements).unwrap();
+        assert!(global(&interpreter, "b").equals(&Object::Number(2.0)));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var b = bytes("héllo");
+            assertEq(hexEncode(b), "68c3a96c6c6f");
+            assertEq(hexDecode("68C3A96C6C6F"), b);
+            assertEq(toString(hexDecode(hexEncode(b))), "héllo");
+            assertEq(hexDecode(""), bytes(""));
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert_eq!(
+            interpret(&mut interpreter, "repr(bytes(\"hi\"));")
+                .unwrap()
+                .to_string(),
+            "<bytes 6869>"
+        );
+        assert!(matches!(
+            interpret(&mut interpreter, "toString(hexDecode(\"ff\"));"),
+            Err(InterpretError::TypeError { message, .. })
+                if message == "toString() got invalid UTF-8."
+        ));
+        assert!(interpret(&mut interpreter, "hexDecode(\"abc\");").is_err());
+        assert!(interpret(&mut interpreter, "hexDecode(\"+1\");").is_err());
+        assert!(interpret(&mut interpreter, "hexEncode(\"ab\");").is_err());
+    }
+
+    #[test]
+    fn test_line_directives_reusing_lines() {
+        let mut interpreter = Interpreter::new();
+        let source = "var a = \"global\";\nvar b;\nvar c;\n\
+                      { var a = \"local\";\n#line 5\n  { b = a; }\n}\n\
+                      #line 5\n{  {c = a; }}";
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "b").equals(&Object::from("local")));
+        assert!(global(&interpreter, "c").equals(&Object::from("global")));
+    }
+
+    #[test]
+    fn test_memoize() {
+        let mut interpreter = Interpreter::new();
+        let source = r#"
+            var calls = 0;
+            fun fib(n) {
+                calls = calls + 1;
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            fib = memoize(fib);
+            var first = fib(30);
+            var firstCalls = calls;
+            var second = fib(30);
+        "#;
+        interpret(&mut interpreter, source).unwrap();
+        assert!(global(&interpreter, "first").equals(&Object::Number(832040.0)));
+        assert!(
+            global(&interpreter, "second").equals(&Object::Number(832040.0))
+        );
+        assert!(
+            global(&interpreter, "firstCalls").equals(&Object::Number(31.0))
+        );
+        assert!(global(&interpreter, "calls").equals(&Object::Number(31.0)));
+        assert!(matches!(
+            interpret(&mut interpreter, "memoize(1);"),
+            Err(InterpretError::TypeError { message, .. })
+                if message == "memoize() expects a function."
+        ));
+    }
+}
@@ -0,0 +1,709 @@
+pub mod ast;
+pub mod ast_printer;
+pub mod diagnostic;
+pub mod environment;
+pub mod interpreter;
+pub mod lexer;
+pub mod native;
+pub mod object;
+pub mod parser;
+pub mod resolver;
+pub mod shared;
+pub mod token;
+
+use ast::Stmt;
+use diagnostic::{Diagnostic, Severity};
+use interpreter::{InterpretError, Interpreter};
+use lexer::{LexError, Lexer};
+use object::Object;
+use parser::{ParseError, Parser};
+use resolver::{ResolveError, Resolver};
+use std::{
+    error,
+    fs::{read_to_string, OpenOptions},
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use token::Token;
+
+pub struct Lox {
+    pub interpreter: Interpreter,
+    // Reports how long each phase of a file run took on stderr.
+    pub time: bool,
+    pub prompt: String,
+    // Printed once when the REPL starts, if set.
+    pub banner: Option<String>,
+    // File the REPL loads earlier input from and appends new input to.
+    pub history: Option<PathBuf>,
+    // Passed on to the parser; see `Parser::strict_braces` and
+    // `Parser::loop_bindings`.
+    pub strict_braces: bool,
+    pub loop_bindings: bool,
+    timings: Vec<(&'static str, Duration)>,
+}
+
+const PROMPT: &str = "> ";
+const HELP: &str =
+    "Enter Lox statements to run them; `return <expr>;` shows a value.
+:help  show this message
+:history  list the input entered so far
+:quit  exit the REPL";
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Underlines the token on its line of the input it was lexed from. A
+// token renumbered by `#line` gets no excerpt, since the line shown would
+// not be the one the error names. A token that runs onto later lines is
+// only underlined up to the end of the first one.
+fn source_context(token: &Token) -> Option<String> {
+    let origin = token.origin.as_ref().filter(|o| !o.is_renumbered())?;
+    let line = origin.source.lines().nth(token.line.checked_sub(1)?)?;
+    let end = if token.end_line == token.line {
+        token.end_column
+    } else {
+        line.chars().count() + 1
+    };
+    // Tabs are kept so the caret lines up however wide they display.
+    let indent: String = line
+        .chars()
+        .take(token.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = end.saturating_sub(token.column).max(1);
+    Some(format!("{}\n{}{}", line, indent, "^".repeat(width)))
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+            time: false,
+            prompt: PROMPT.to_string(),
+            banner: Some(format!(
+                "lox-rs {} (type :help for help)",
+                env!("CARGO_PKG_VERSION")
+            )),
+            history: None,
+            strict_braces: false,
+            loop_bindings: false,
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn run_file(&mut self, path: &str) {
+        let source = read_to_string(path).unwrap();
+        match self.run(&source) {
+            Ok(()) if self.time => {
+                for (phase, elapsed) in &self.timings {
+                    eprintln!("{:<10} {:?}", phase, elapsed);
+                }
+            }
+            Ok(()) => {}
+            Err(e) => eprintln!("{}", self.render_error(e.as_ref())),
+        }
+    }
+
+    // Runs source given directly, such as from the command line. A bare
+    // expression has its value printed, as the REPL would; anything else
+    // runs as a program. Returns the exit status: 0, 65 for source that
+    // doesn't compile, or 70 for an error at runtime.
+    pub fn run_source(&mut self, source: &str) -> i32 {
+        let result = match self.eval_expr(source) {
+            Ok(value) => {
+                println!("{}", self.interpreter.stringify(&value));
+                Ok(())
+            }
+            Err(e) if e.is::<LexError>() || e.is::<ParseError>() => {
+                self.run(source)
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", self.render_error(e.as_ref()));
+                if e.is::<InterpretError>() {
+                    70
+                } else {
+                    65
+                }
+            }
+        }
+    }
+
+    // Prints the diagnostics for a file to stderr and reports whether it is
+    // free of errors; warnings alone still pass. Fails only if the file
+    // can't be read.
+    pub fn check_file(&self, path: &str) -> io::Result<bool> {
+        let source = read_to_string(path)?;
+        let diagnostics = self.diagnostics(&source);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        Ok(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Severity::Error))
+    }
+
+    // Runs the file, then each top-level `test` in it, printing a line per
+    // test and a summary. Reports whether every test passed.
+    pub fn test_file(&mut self, path: &str) -> bool {
+        let source = match read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                return false;
+            }
+        };
+        match self.run_tests(&source) {
+            Ok((passed, failed)) => {
+                println!("{} passed; {} failed", passed, failed);
+                failed == 0
+            }
+            Err(e) => {
+                eprintln!("{}", self.render_error(e.as_ref()));
+                false
+            }
+        }
+    }
+
+    pub fn run_prompt(&mut self) {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        self.repl(&mut stdin.lock(), &mut stdout.lock()).unwrap();
+    }
+
+    // Drives the prompt over any reader and writer, stopping at `:quit` or
+    // the end of input.
+    pub fn repl(
+        &mut self,
+        reader: &mut impl BufRead,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        if let Some(banner) = &self.banner {
+            writeln!(writer, "{}", banner)?;
+        }
+        let mut history = match &self.history {
+            Some(path) if path.exists() => {
+                read_to_string(path)?.lines().map(str::to_string).collect()
+            }
+            _ => Vec::new(),
+        };
+        loop {
+            write!(writer, "{}", self.prompt)?;
+            writer.flush()?;
+
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            match line.trim() {
+                ":quit" => return Ok(()),
+                ":help" => writeln!(writer, "{}", HELP)?,
+                ":history" => {
+                    for (number, entry) in history.iter().enumerate() {
+                        writeln!(writer, "{:>4}  {}", number + 1, entry)?;
+                    }
+                }
+                "" => {}
+                entry => {
+                    // Losing history shouldn't end the session.
+                    if let Err(e) = self.record(entry) {
+                        eprintln!("Could not save history: {}", e);
+                    }
+                    history.push(entry.to_string());
+                    match self.run_line(&line) {
+                        Ok(Some(value)) => writeln!(
+                            writer,
+                            "{}",
+                            self.interpreter.stringify(&value)
+                        )?,
+                        Ok(None) => {}
+                        Err(e) => {
+                            eprintln!("{}", self.render_error(e.as_ref()))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn record(&self, entry: &str) -> io::Result<()> {
+        match &self.history {
+            Some(path) => {
+                let mut file =
+                    OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", entry)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Renders the error followed by the offending source line and a caret
+    // under the column of the token it points at, when there is one.
+    pub fn render_error(&self, error: &(dyn error::Error + 'static)) -> String {
+        let token = if let Some(e) = error.downcast_ref::<ParseError>() {
+            Some(e.token())
+        } else if let Some(e) = error.downcast_ref::<ResolveError>() {
+            Some(e.token())
+        } else if let Some(e) = error.downcast_ref::<InterpretError>() {
+            e.token()
+        } else {
+            None
+        };
+        let file = token.and_then(Token::file);
+        let message = match file {
+            Some(file) => format!("{}: {}", file, error),
+            None => error.to_string(),
+        };
+        match token.and_then(source_context) {
+            Some(context) => format!("{}\n{}", message, context),
+            None => message,
+        }
+    }
+
+    // Evaluates a single expression against the current globals and hands
+    // back its value instead of printing it.
+    pub fn eval_expr(
+        &mut self,
+        source: &str,
+    ) -> Result<Object, Box<dyn error::Error>> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan()?;
+        let expr = Parser::new(tokens).parse_expression()?;
+        Resolver::new(&mut self.interpreter).resolve_expr(&expr)?;
+        Ok(self.interpreter.evaluate(&expr)?)
+    }
+
+    // Lexes, parses and resolves the source with lints on, without running
+    // it, and collects every error and warning found along the way. Lexing
+    // and parsing recover and keep going; resolving stops at its first
+    // error.
+    pub fn diagnostics(&self, source: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut tokens = Vec::new();
+        for token in Lexer::new(source) {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(error) => diagnostics.push(error.into()),
+            }
+        }
+
+        let (statements, errors) = Parser::new(&tokens).parse_recovering();
+        diagnostics.extend(errors.into_iter().map(Diagnostic::from));
+
+        let mut interpreter = Interpreter::new();
+        let mut resolver = Resolver::with_lints(&mut interpreter);
+        let result = resolver.resolve_stmts(&statements);
+        let warnings = std::mem::take(&mut resolver.warnings);
+        diagnostics.extend(warnings.into_iter().map(Diagnostic::from));
+        if let Err(error) = result {
+            diagnostics.push(error.into());
+        }
+        diagnostics
+    }
+
+    fn run(&mut self, source: &str) -> Result<(), Box<dyn error::Error>> {
+        self.execute(source, false).map(|_| ())
+    }
+
+    // Runs one line of REPL input, yielding the value of a trailing
+    // expression statement or a top-level `return` for the prompt to
+    // display.
+    fn run_line(
+        &mut self,
+        line: &str,
+    ) -> Result<Option<Object>, Box<dyn error::Error>> {
+        self.execute(line, true)
+    }
+
+    fn run_tests(
+        &mut self,
+        source: &str,
+    ) -> Result<(usize, usize), Box<dyn error::Error>> {
+        let tokens = Lexer::new(source).scan()?.to_vec();
+        let statements = self.parser(&tokens, source).parse()?;
+        Resolver::new(&mut self.interpreter).resolve_stmts(&statements)?;
+        self.interpreter.interpret(&statements)?;
+
+        let (mut passed, mut failed) = (0, 0);
+        for statement in &statements {
+            if let Stmt::Test { name, body } = statement {
+                match self.interpreter.run_test(body) {
+                    Ok(()) => {
+                        passed += 1;
+                        println!("test {} ... ok", name.lexeme);
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        println!("test {} ... FAILED", name.lexeme);
+                        eprintln!("{}", self.render_error(&e));
+                    }
+                }
+            }
+        }
+        Ok((passed, failed))
+    }
+
+    fn parser<'a>(&self, tokens: &'a [Token], source: &'a str) -> Parser<'a> {
+        Parser::with_source(tokens, source)
+            .strict_braces(self.strict_braces)
+            .loop_bindings(self.loop_bindings)
+    }
+
+    fn lap(&mut self, phase: &'static str, start: &mut Instant) {
+        self.timings.push((phase, start.elapsed()));
+        *start = Instant::now();
+    }
+
+    fn execute(
+        &mut self,
+        source: &str,
+        repl: bool,
+    ) -> Result<Option<Object>, Box<dyn error::Error>> {
+        self.timings.clear();
+        let mut start = Instant::now();
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan()?;
+        self.lap("lex", &mut start);
+
+        let mut parser = self.parser(tokens, source);
+        let statements = parser.parse()?;
+        self.lap("parse", &mut start);
+
+        let mut resolver = if repl {
+            Resolver::for_repl(&mut self.interpreter)
+        } else {
+            Resolver::new(&mut self.interpreter)
+        };
+        resolver.resolve_stmts(&statements)?;
+        self.lap("resolve", &mut start);
+
+        let ends_with_expression =
+            matches!(statements.last(), Some(Stmt::Expression { .. }));
+        let result = self.interpreter.interpret(&statements);
+        self.lap("interpret", &mut start);
+        match result {
+            Ok(value) if ends_with_expression => Ok(Some(value)),
+            Ok(_) => Ok(None),
+            Err(InterpretError::Return { value }) => Ok(Some(value)),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::Severity, object::Object, resolver::ResolveError, Lox,
+    };
+    use std::{error, fs::read_to_string};
+
+    fn run_case(path: &str) -> Result<(), Box<dyn error::Error>> {
+        let mut lox = Lox::new();
+        let source = read_to_string(path)?;
+        lox.run(&source)
+    }
+
+    #[test]
+    fn test_enclosing() {
+        assert!(run_case("./examples/enclosing.lox").is_ok())
+    }
+
+    #[test]
+    fn test_for() {
+        assert!(run_case("./examples/for.lox").is_ok())
+    }
+
+    #[test]
+    fn test_or_and() {
+        assert!(run_case("./examples/or-and.lox").is_ok())
+    }
+
+    #[test]
+    fn test_fib() {
+        assert!(run_case("./examples/fib.lox").is_ok())
+    }
+
+    #[test]
+    fn test_closure() {
+        assert!(run_case("./examples/closure.lox").is_ok())
+    }
+
+    #[test]
+    fn test_inner_outer() {
+        assert!(run_case("./examples/inner_outer.lox").is_ok())
+    }
+
+    #[test]
+    fn test_hoisting() {
+        assert!(run_case("./examples/hoisting.lox").is_ok())
+    }
+
+    #[test]
+    fn test_hoisted_function_after_variable() {
+        let mut lox = Lox::new();
+        let error = lox.run("var f = 1;\nfun f() {}").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ResolveError>(),
+            Some(ResolveError::AlreadyDeclared { token })
+                if token.lexeme == "f" && token.line == 2
+        ));
+        assert!(lox.run("fun g() {}\nvar g = 1;\nprint g;").is_ok());
+        assert!(lox.run("{ var h = 1; fun h() {} }").is_err());
+        assert!(lox.run("fun m() {}\nvar m = 1;\nfun m() {}").is_ok());
+        assert!(lox.run_line("var k = 1;").is_ok());
+        assert!(lox.run_line("fun k() {}").is_ok());
+    }
+
+    #[test]
+    fn test_redefined_function_runs_in_order() {
+        let mut lox = Lox::new();
+        let source = "var before = f();\nfun f() { return 1; }\n\
+                      var first = f();\nfun f() { return 2; }\n\
+                      var second = f();";
+        lox.run(source).unwrap();
+        for (name, expected) in
+            [("before", 1.0), ("first", 1.0), ("second", 2.0)]
+        {
+            let value = lox.eval_expr(name).unwrap();
+            assert!(value.equals(&Object::Number(expected)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_repl_redefine_global() {
+        let mut lox = Lox::new();
+        assert!(lox.run_line("var x = 1;").is_ok());
+        assert!(lox.run_line("var x = 2;").is_ok());
+        let value = lox.run_line("x;").unwrap();
+        assert!(value.is_some_and(|value| value.equals(&Object::Number(2.0))));
+        assert!(lox.run_line("const y = 1;").is_ok());
+        assert!(lox.run_line("y = 2;").is_err());
+        assert!(lox.run_line("{ var y = 1; var y = 2; }").is_err());
+    }
+
+    #[test]
+    fn test_line_directive_in_errors() {
+        let mut lox = Lox::new();
+        let source = "var a = 1;\n#line 100 \"gen.lox\"\n\nprint a + nil;";
+        let error = lox.run(source).unwrap_err();
+        assert_eq!(
+            lox.render_error(error.as_ref()),
+            "gen.lox: TypeError (line 101 at +) \
+             Cannot use nil in arithmetic (right operand)."
+        );
+    }
+
+    #[test]
+    fn test_render_error_context() {
+        let mut lox = Lox::new();
+        let error = lox.run("var a = 1;\nprint a + \"x\";").unwrap_err();
+        let rendered = lox.render_error(error.as_ref());
+        let mut lines = rendered.lines();
+        assert!(lines.next().unwrap().starts_with("TypeError (line 2 at +)"));
+        assert_eq!(lines.next(), Some("print a + \"x\";"));
+        assert_eq!(lines.next(), Some("        ^"));
+    }
+
+    #[test]
+    fn test_render_error_from_earlier_input() {
+        let mut lox = Lox::new();
+        lox.run_line("fun f() { return 1 + nil; }").unwrap();
+        let error = lox.run_line("var x = 1;\nprint f();").unwrap_err();
+        let rendered = lox.render_error(error.as_ref());
+        let mut lines = rendered.lines().skip(1);
+        assert_eq!(lines.next(), Some("fun f() { return 1 + nil; }"));
+        assert_eq!(lines.next(), Some("                   ^"));
+    }
+
+    #[test]
+    fn test_render_error_after_tabs() {
+        let mut lox = Lox::new();
+        let error = lox.run("\tprint 1 +\tnil;").unwrap_err();
+        let rendered = lox.render_error(error.as_ref());
+        let mut lines = rendered.lines().skip(1);
+        assert_eq!(lines.next(), Some("\tprint 1 +\tnil;"));
+        assert_eq!(lines.next(), Some("\t        ^"));
+    }
+
+    #[test]
+    fn test_render_error_multiline_string() {
+        let mut lox = Lox::new();
+        let error = lox.run("var s = \"a\nb\";\nprint s - 1;").unwrap_err();
+        let rendered = lox.render_error(error.as_ref());
+        let mut lines = rendered.lines().skip(1);
+        assert_eq!(lines.next(), Some("print s - 1;"));
+        assert_eq!(lines.next(), Some("        ^"));
+
+        let error = lox.run("print \"x\";\nprint \"a\nb\" 1;").unwrap_err();
+        let rendered = lox.render_error(error.as_ref());
+        assert!(rendered.starts_with("Missing semicolon (line 2 after a"));
+        let mut lines = rendered.lines().rev();
+        assert_eq!(lines.next(), Some("      ^^"));
+        assert_eq!(lines.next(), Some("print \"a"));
+    }
+
+    #[test]
+    fn test_repl_top_level_return() {
+        let mut lox = Lox::new();
+        let value = lox.run_line("return 5;").unwrap();
+        assert!(value.is_some_and(|value| value.equals(&Object::Number(5.0))));
+        assert!(lox.run_line("var x = 1;").unwrap().is_none());
+        let value = lox.run_line("x + 1;").unwrap();
+        assert!(value.is_some_and(|value| value.equals(&Object::Number(2.0))));
+
+        let error = lox.run("return 5;").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ResolveError>(),
+            Some(ResolveError::TopLevelReturn { .. })
+        ));
+    }
+
+    #[test]
+    fn test_repl_meta_commands() {
+        let mut lox = Lox::new();
+        lox.prompt = "lox> ".to_string();
+        lox.banner = Some("hello".to_string());
+        let mut input = "return 1 + 1;\n:help\n:quit\nreturn 3;\n".as_bytes();
+        let mut output = Vec::new();
+        lox.repl(&mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("hello\nlox> 2\nlox> "), "{}", output);
+        assert!(output.contains(":quit  exit the REPL"), "{}", output);
+        assert!(output.ends_with("lox> "), "{}", output);
+        assert!(!output.contains('3'), "{}", output);
+    }
+
+    #[test]
+    fn test_repl_history() {
+        let path = std::env::temp_dir().join("lox-rs-repl-history.txt");
+        let _ = std::fs::remove_file(&path);
+        let session = |input: &str| {
+            let mut lox = Lox::new();
+            lox.banner = None;
+            lox.prompt = String::new();
+            lox.history = Some(path.clone());
+            let mut output = Vec::new();
+            lox.repl(&mut input.as_bytes(), &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        session("var x = 1;\n\n:help\nx + 1;\n");
+        let output = session("print 2;\n:history\n");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(output, "   1  var x = 1;\n   2  x + 1;\n   3  print 2;\n");
+    }
+
+    #[test]
+    fn test_repl_history_write_failure() {
+        let dir = std::env::temp_dir().join("lox-rs-no-such-dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut lox = Lox::new();
+        lox.banner = None;
+        lox.prompt = String::new();
+        lox.history = Some(dir.join("history.txt"));
+        let mut output = Vec::new();
+        lox.repl(&mut "var x = 1;\nx + 1;\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_repl_stops_at_end_of_input() {
+        let mut lox = Lox::new();
+        lox.banner = None;
+        let mut output = Vec::new();
+        lox.repl(&mut "var x = 1;".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> > ");
+    }
+
+    #[test]
+    fn test_parser_options() {
+        let source = "
+            var get;
+            for (var i = 0; i < 2; i = i + 1) {
+                fun f() { return i; }
+                if (i == 0) { get = f; }
+            }
+            var first = get();
+        ";
+        let mut lox = Lox::new();
+        lox.run(source).unwrap();
+        assert!(lox.eval_expr("first").unwrap().equals(&Object::Number(2.0)));
+
+        let mut lox = Lox::new();
+        lox.loop_bindings = true;
+        lox.strict_braces = true;
+        lox.run(source).unwrap();
+        assert!(lox.eval_expr("first").unwrap().equals(&Object::Number(0.0)));
+        assert!(lox.run("if (true) print 1;").is_err());
+    }
+
+    #[test]
+    fn test_eval_expr() {
+        let mut lox = Lox::new();
+        let value = lox.eval_expr("1 + 2 * 3").unwrap();
+        assert!(value.equals(&Object::Number(7.0)));
+        lox.run("var x = 4;").unwrap();
+        assert!(lox
+            .eval_expr("x * x")
+            .unwrap()
+            .equals(&Object::Number(16.0)));
+        assert!(lox.eval_expr("1 +").is_err());
+        assert!(lox.eval_expr("1 2").is_err());
+        assert!(lox.eval_expr("1;").is_err());
+    }
+
+    #[test]
+    fn test_diagnostics_collects_every_phase() {
+        let lox = Lox::new();
+        let source = "var a = 1 @;\nprint clock;\nvar = 2;\nprint a;\n";
+        let diagnostics = lox.diagnostics(source);
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.line, d.code))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Error, 1, "unexpected-character"),
+                (Severity::Error, 3, "unexpected-token"),
+                (Severity::Warning, 2, "printing-callable"),
+            ]
+        );
+        assert!(lox.diagnostics("var a = 1;\nprint a;").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_escape_is_one_diagnostic() {
+        let lox = Lox::new();
+        for source in [
+            "var s = \"a\\qb; c (\";\nprint s;",
+            "var s = \"\\q ${1 + 2} w\";\nprint s;",
+            "var s = \"\\u{12\";\nprint s;",
+        ] {
+            let diagnostics = lox.diagnostics(source);
+            let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, vec!["invalid-escape"], "{}", source);
+        }
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_run_on_spawned_thread() {
+        let lox = Lox::new();
+        let handle = std::thread::spawn(move || {
+            let mut lox = lox;
+            lox.run_line("fun sq(n) { return n * n; } return sq(7);")
+                .unwrap()
+                .map(|value| value.to_string())
+        });
+        assert_eq!(handle.join().unwrap().as_deref(), Some("49"));
+    }
+}
@@ -1,123 +1,47 @@
-mod ast;
-mod ast_printer;
-mod environment;
-mod interpreter;
-mod lexer;
-mod object;
-mod parser;
-mod resolver;
-mod token;
+use lox_rs::Lox;
+use std::process;
 
-use interpreter::Interpreter;
-use lexer::Lexer;
-use parser::Parser;
-use resolver::Resolver;
-use std::{
-    error,
-    fs::read_to_string,
-    io::{self, BufRead, Write},
-};
+const USAGE: &str = "Usage: lox-rs [--time | --check] [script]
+       lox-rs --eval <source>
+       lox-rs --test <script>
+       lox-rs --repl-history <file>";
 
-pub struct Lox {
-    pub interpreter: Interpreter,
-}
-
-const PROMPT: &'static str = "> ";
-
-impl Lox {
-    pub fn new() -> Self {
-        Self {
-            interpreter: Interpreter::new(),
+fn main() {
+    let mut lox = Lox::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--time", path] => {
+            lox.time = true;
+            lox.run_file(path);
         }
-    }
-
-    pub fn run_file(&mut self, path: &str) {
-        let source = read_to_string(path).unwrap();
-        if let Err(e) = self.run(&source) {
-            eprintln!("{}", e);
+        ["--check", path] => match lox.check_file(path) {
+            Ok(true) => {}
+            Ok(false) => process::exit(65),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(66);
+            }
+        },
+        ["--eval", source] => {
+            let status = lox.run_source(source);
+            if status != 0 {
+                process::exit(status);
+            }
         }
-    }
-
-    pub fn run_prompt(&mut self) {
-        let stdin = io::stdin();
-        let stdout = io::stdout();
-        let mut reader = stdin.lock();
-        let mut writer = stdout.lock();
-
-        loop {
-            writer.write(PROMPT.as_bytes()).unwrap();
-            writer.flush().unwrap();
-
-            let mut line = String::new();
-            reader.read_line(&mut line).unwrap();
-
-            if let Err(e) = self.run(&line) {
-                eprintln!("{}", e);
+        ["--test", path] => {
+            if !lox.test_file(path) {
+                process::exit(1);
             }
         }
-    }
-
-    fn run(&mut self, source: &str) -> Result<(), Box<dyn error::Error>> {
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.scan()?;
-
-        let mut parser = Parser::new(tokens);
-        let statements = parser.parse()?;
-
-        let mut resolver = Resolver::new(&mut self.interpreter);
-        resolver.resolve_stmts(&statements)?;
-
-        self.interpreter.interpret(&statements)?;
-        Ok(())
-    }
-}
-
-fn main() {
-    let mut lox = Lox::new();
-    match std::env::args().nth(1) {
-        Some(path) => lox.run_file(&path),
-        None => lox.run_prompt(),
+        [path] => lox.run_file(path),
+        ["--repl-history", path] => {
+            lox.history = Some(path.into());
+            lox.run_prompt();
+        }
+        [] => lox.run_prompt(),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(64);
+        }
     };
 }
-
-#[cfg(test)]
-mod tests {
-    use crate::Lox;
-    use std::{error, fs::read_to_string};
-
-    fn run_case(path: &str) -> Result<(), Box<dyn error::Error>> {
-        let mut lox = Lox::new();
-        let source = read_to_string(path)?;
-        lox.run(&source)
-    }
-
-    #[test]
-    fn test_enclosing() {
-        assert!(run_case("./examples/enclosing.lox").is_ok())
-    }
-
-    #[test]
-    fn test_for() {
-        assert!(run_case("./examples/for.lox").is_ok())
-    }
-
-    #[test]
-    fn test_or_and() {
-        assert!(run_case("./examples/or-and.lox").is_ok())
-    }
-
-    #[test]
-    fn test_fib() {
-        assert!(run_case("./examples/fib.lox").is_ok())
-    }
-
-    #[test]
-    fn test_closure() {
-        assert!(run_case("./examples/closure.lox").is_ok())
-    }
-
-    #[test]
-    fn test_inner_outer() {
-        assert!(run_case("./examples/inner_outer.lox").is_ok())
-    }
-}
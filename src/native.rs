@@ -0,0 +1,455 @@
+use crate::{
+    interpreter::{InterpretError, Interpreter, Result},
+    object::{self, Function, Object},
+    shared::{Lock, Shared},
+    token::Token,
+};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{self, Write},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+fn type_error(paren: &Token, message: &str) -> InterpretError {
+    InterpretError::TypeError {
+        token: paren.clone(),
+        message: message.to_string(),
+    }
+}
+
+pub fn clock(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    _args: &[Object],
+) -> Result<Object> {
+    Ok(Object::Number(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Could not retrieve time.")
+            .as_millis() as f64,
+    ))
+}
+
+pub fn sleep(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match args[0] {
+        Object::Number(ms) if ms >= 0.0 && ms.is_finite() => {
+            thread::sleep(Duration::from_millis(ms as u64));
+            Ok(Object::Nil)
+        }
+        _ => Err(type_error(
+            paren,
+            "sleep() expects a non-negative number of milliseconds.",
+        )),
+    }
+}
+
+pub fn assert_eq(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let (actual, expected) = (&args[0], &args[1]);
+    if actual.equals(expected) {
+        Ok(Object::Nil)
+    } else {
+        Err(InterpretError::AssertionFailed {
+            token: paren.clone(),
+            message: format!("expected {} but got {}", expected, actual),
+        })
+    }
+}
+
+fn numbers(name: &str, paren: &Token, args: &[Object]) -> Result<Vec<f64>> {
+    if args.is_empty() {
+        return Err(type_error(
+            paren,
+            &format!("{}() expects at least one argument.", name),
+        ));
+    }
+    args.iter()
+        .map(|arg| match arg {
+            Object::Number(n) => Ok(*n),
+            _ => Err(type_error(
+                paren,
+                &format!("{}() expects only numbers.", name),
+            )),
+        })
+        .collect()
+}
+
+pub fn min(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let numbers = numbers("min", paren, args)?;
+    Ok(Object::Number(
+        numbers.into_iter().fold(f64::INFINITY, f64::min),
+    ))
+}
+
+pub fn max(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let numbers = numbers("max", paren, args)?;
+    Ok(Object::Number(
+        numbers.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    ))
+}
+
+pub fn contains(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match (&args[0], &args[1]) {
+        (Object::String(haystack), Object::String(needle)) => {
+            Ok(Object::Boolean(haystack.contains(needle.as_str())))
+        }
+        (collection, value) => Err(type_error(
+            paren,
+            &format!(
+                "contains() cannot search a {} for a {}.",
+                collection.type_name(),
+                value.type_name()
+            ),
+        )),
+    }
+}
+
+pub fn format(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let n = match args[0] {
+        Object::Number(n) => n,
+        _ => return Err(type_error(paren, "format() expects a number.")),
+    };
+    match args[1] {
+        Object::Number(d) if d.fract() == 0.0 && (0.0..=100.0).contains(&d) => {
+            Ok(Object::String(format!("{:.*}", d as usize, n)))
+        }
+        _ => Err(type_error(
+            paren,
+            "format() expects a whole number of decimals from 0 to 100.",
+        )),
+    }
+}
+
+pub fn approx_eq(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match (&args[0], &args[1], &args[2]) {
+        (Object::Number(a), Object::Number(b), Object::Number(epsilon))
+            if *epsilon >= 0.0 =>
+        {
+            Ok(Object::Boolean((a - b).abs() <= *epsilon))
+        }
+        _ => Err(type_error(
+            paren,
+            "approxEq() expects two numbers and a non-negative epsilon.",
+        )),
+    }
+}
+
+// Quotes a string so that lexing the result gives back the same string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            '\x1b' => quoted.push_str("\\e"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '$' if chars.peek() == Some(&'{') => quoted.push_str("\\$"),
+            c if c.is_ascii_control() => {
+                quoted.push_str(&format!("\\x{:02x}", c as u8))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn repr(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    Ok(Object::String(match &args[0] {
+        Object::String(s) => quote(s),
+        other => interpreter.stringify(other),
+    }))
+}
+
+pub fn ord(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let s = String::try_from(args[0].clone())?;
+    match s.chars().next() {
+        Some(c) => Ok(Object::Number(c as u32 as f64)),
+        None => Err(type_error(paren, "ord() expects a non-empty string.")),
+    }
+}
+
+pub fn chr(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let n = f64::try_from(args[0].clone())?;
+    let c = if n.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&n) {
+        char::from_u32(n as u32)
+    } else {
+        None
+    };
+    match c {
+        Some(c) => Ok(Object::String(c.to_string())),
+        None => Err(type_error(
+            paren,
+            "chr() expects a valid Unicode code point.",
+        )),
+    }
+}
+
+// Reads a whole number written in the given base, with an optional sign.
+// Text that isn't one gives nil rather than an error.
+pub fn parse_int(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let (text, radix) = match (&args[0], &args[1]) {
+        (Object::String(text), Object::Number(radix))
+            if radix.fract() == 0.0 && (2.0..=36.0).contains(radix) =>
+        {
+            (text.trim(), *radix as u32)
+        }
+        _ => {
+            return Err(type_error(
+                paren,
+                "parseInt() expects a radix from 2 to 36.",
+            ))
+        }
+    };
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+    };
+    if digits.is_empty() {
+        return Ok(Object::Nil);
+    }
+    let mut n = 0.0;
+    for c in digits.chars() {
+        match c.to_digit(radix) {
+            Some(digit) => n = n * radix as f64 + digit as f64,
+            None => return Ok(Object::Nil),
+        }
+    }
+    Ok(Object::Number(sign * n))
+}
+
+// Reads a decimal number; text that isn't one gives nil.
+pub fn parse_float(
+    _interpreter: &mut Interpreter,
+    _paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let text = match &args[0] {
+        Object::String(text) => text.trim(),
+        _ => return Ok(Object::Nil),
+    };
+    match text.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(Object::Number(n)),
+        _ => Ok(Object::Nil),
+    }
+}
+
+// Like `print`, but without the trailing newline.
+pub fn write(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", interpreter.stringify(&args[0]))
+        .and_then(|_| stdout.flush())
+        .expect("Could not write to stdout.");
+    Ok(Object::Nil)
+}
+
+// Like `print`, but to stderr, keeping diagnostics apart from output.
+pub fn eprint(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    eprintln!("{}", interpreter.stringify(&args[0]));
+    Ok(Object::Nil)
+}
+
+pub fn random(
+    interpreter: &mut Interpreter,
+    _paren: &Token,
+    _args: &[Object],
+) -> Result<Object> {
+    Ok(Object::Number(interpreter.next_random()))
+}
+
+// A whole number from `lo` to `hi`, both included.
+pub fn random_int(
+    interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match (&args[0], &args[1]) {
+        (Object::Number(lo), Object::Number(hi))
+            if lo.fract() == 0.0 && hi.fract() == 0.0 && lo <= hi =>
+        {
+            let span = hi - lo + 1.0;
+            let offset = (interpreter.next_random() * span).floor();
+            Ok(Object::Number(lo + offset))
+        }
+        _ => Err(type_error(
+            paren,
+            "randomInt() expects two whole numbers with lo <= hi.",
+        )),
+    }
+}
+
+pub fn bytes(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match &args[0] {
+        Object::String(s) => {
+            Ok(Object::Bytes(Shared::new(s.as_bytes().to_vec())))
+        }
+        _ => Err(type_error(paren, "bytes() expects a string.")),
+    }
+}
+
+pub fn hex_encode(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match &args[0] {
+        Object::Bytes(b) => Ok(Object::String(object::hex(b))),
+        _ => Err(type_error(paren, "hexEncode() expects bytes.")),
+    }
+}
+
+pub fn hex_decode(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let text = match &args[0] {
+        Object::String(text) => text.as_bytes(),
+        _ => return Err(type_error(paren, "hexDecode() expects a string.")),
+    };
+    if text.len() % 2 != 0 {
+        return Err(type_error(
+            paren,
+            "hexDecode() expects an even number of digits.",
+        ));
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 2);
+    for pair in text.chunks(2) {
+        if !pair.iter().all(u8::is_ascii_hexdigit) {
+            return Err(type_error(paren, "hexDecode() expects hex digits."));
+        }
+        let pair = std::str::from_utf8(pair).unwrap();
+        bytes.push(u8::from_str_radix(pair, 16).unwrap());
+    }
+    Ok(Object::Bytes(Shared::new(bytes)))
+}
+
+pub fn to_string(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    match &args[0] {
+        Object::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) => Ok(Object::String(s.to_string())),
+            Err(_) => Err(type_error(paren, "toString() got invalid UTF-8.")),
+        },
+        _ => Err(type_error(paren, "toString() expects bytes.")),
+    }
+}
+
+// A cache key naming each argument by type and exact value. Functions
+// compare by identity, which a string can't capture, so a call that
+// passes one isn't cached.
+fn memo_key(args: &[Object]) -> Option<String> {
+    let mut key = String::new();
+    for arg in args {
+        let value = match arg {
+            Object::Number(n) => format!("{:?}", n),
+            Object::String(s) => quote(s),
+            Object::Callable(_) => return None,
+            other => other.to_string(),
+        };
+        key.push_str(&format!("{}:{},", arg.type_name(), value));
+    }
+    Some(key)
+}
+
+pub fn memoize(
+    _interpreter: &mut Interpreter,
+    paren: &Token,
+    args: &[Object],
+) -> Result<Object> {
+    let function = match &args[0] {
+        Object::Callable(function) => function.clone(),
+        _ => return Err(type_error(paren, "memoize() expects a function.")),
+    };
+    let arity = function.arity();
+    let signature = match &function {
+        Function::Native { signature, .. } => *signature,
+        Function::User { .. } => None,
+    };
+    let cache: Lock<HashMap<String, Object>> = Lock::new(HashMap::new());
+    let body =
+        move |interpreter: &mut Interpreter, paren: &Token, args: &[Object]| {
+            let key = memo_key(args);
+            if let Some(value) =
+                key.as_ref().and_then(|k| cache.borrow().get(k).cloned())
+            {
+                return Ok(value);
+            }
+            // The cache isn't borrowed during the call, so a recursive
+            // function can go back through the memoized one.
+            let value = function.call(interpreter, paren, args)?;
+            if let Some(key) = key {
+                cache.borrow_mut().insert(key, value.clone());
+            }
+            Ok(value)
+        };
+    Ok(Object::Callable(Function::Native {
+        arity,
+        signature,
+        body: Shared::new(body),
+    }))
+}
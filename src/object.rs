@@ -1,9 +1,10 @@
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{convert::TryFrom, error::Error, fmt};
 
 use crate::{
     ast::Stmt,
     environment::Environment,
-    interpreter::{Interpreter, InterpretError},
+    interpreter::{InterpretError, Interpreter},
+    shared::{Lock, NativeBody, Shared},
     token::Token,
 };
 
@@ -13,10 +14,25 @@ pub enum Object {
     Nil,
     Number(f64),
     String(String),
+    Bytes(Shared<Vec<u8>>),
     Callable(Function),
+    // Placeholder for `var x;` when the interpreter checks for reads
+    // before assignment; it never escapes a variable lookup.
+    Uninitialized,
 }
 
 impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Boolean(_) => "boolean",
+            Object::Nil | Object::Uninitialized => "nil",
+            Object::Number(_) => "number",
+            Object::String(_) => "string",
+            Object::Bytes(_) => "bytes",
+            Object::Callable(_) => "function",
+        }
+    }
+
     pub fn equals(&self, other: &Object) -> bool {
         match (self, other) {
             (Object::Nil, Object::Nil) => true,
@@ -25,35 +41,167 @@ impl Object {
             (Object::Boolean(left), Object::Boolean(right)) => left == right,
             (Object::Number(left), Object::Number(right)) => left == right,
             (Object::String(left), Object::String(right)) => left == right,
+            (Object::Bytes(left), Object::Bytes(right)) => left == right,
+            (Object::Callable(left), Object::Callable(right)) => {
+                left.is_same(right)
+            }
             _ => false,
         }
     }
 }
 
+impl From<f64> for Object {
+    fn from(n: f64) -> Self {
+        Object::Number(n)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(b: bool) -> Self {
+        Object::Boolean(b)
+    }
+}
+
+impl From<String> for Object {
+    fn from(s: String) -> Self {
+        Object::String(s)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(s: &str) -> Self {
+        Object::String(s.to_string())
+    }
+}
+
+// A value of the wrong type for a conversion out of `Object`. It has no
+// position of its own; natives attach their call's with `at`.
+#[derive(Debug, PartialEq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl ConversionError {
+    pub fn at(self, token: &Token) -> InterpretError {
+        InterpretError::TypeError {
+            token: token.clone(),
+            message: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected a {} but got a {}.", self.expected, self.found)
+    }
+}
+
+impl Error for ConversionError {}
+
+impl TryFrom<Object> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Number(n) => Ok(n),
+            other => Err(ConversionError {
+                expected: "number",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(b) => Ok(b),
+            other => Err(ConversionError {
+                expected: "boolean",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(s) => Ok(s),
+            other => Err(ConversionError {
+                expected: "string",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Object::Nil => "nil".to_string(),
-            Object::Number(n) => n.to_string(),
+            Object::Number(n) => format_number(*n, NumberDisplay::Auto),
             Object::Boolean(b) => b.to_string(),
             Object::String(s) => s.to_string(),
+            Object::Bytes(b) => format!("<bytes {}>", hex(b)),
             Object::Callable(f) => f.to_string(),
+            Object::Uninitialized => "uninitialized".to_string(),
         };
         write!(f, "{}", s)
     }
 }
 
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberDisplay {
+    // The shortest decimal that reads back as the same number, as Lox has
+    // always printed it. Rust never switches that to exponent form, so
+    // for now it matches `Fixed`.
+    Auto,
+    Fixed,
+    Scientific,
+}
+
+pub fn format_number(n: f64, display: NumberDisplay) -> String {
+    // Pin zero explicitly so `0 * -1` prints `-0` like the reference Lox.
+    if n == 0.0 {
+        return if n.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    if display == NumberDisplay::Scientific && n.is_finite() {
+        format!("{:e}", n)
+    } else {
+        n.to_string()
+    }
+}
+
+pub type NativeFn = Shared<dyn NativeBody>;
+
 #[derive(Clone)]
 pub enum Function {
     Native {
-        arity: usize,
-        body: Box<fn(&Vec<Object>) -> Object>,
+        // `None` accepts any number of arguments.
+        arity: Option<usize>,
+        // Type names, as given by `Object::type_name`, that each argument
+        // must have; checked before the body runs.
+        signature: Option<&'static [&'static str]>,
+        body: NativeFn,
     },
     User {
-        name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
-        closure: Rc<RefCell<Environment>>,
+        name: Shared<Token>,
+        params: Shared<[Token]>,
+        body: Shared<[Stmt]>,
+        // Cloning a function shares its captured environment, so copies of
+        // a closure see each other's writes, like any other Lox reference.
+        // Independent state comes from calling the factory again.
+        closure: Shared<Lock<Environment>>,
     },
 }
 
@@ -61,18 +209,23 @@ impl Function {
     pub fn call(
         &self,
         interpreter: &mut Interpreter,
-        arguments: &Vec<Object>,
+        paren: &Token,
+        arguments: &[Object],
     ) -> Result<Object, InterpretError> {
         match self {
-            Function::Native { body, .. } => Ok(body(arguments)),
+            Function::Native { body, .. } => {
+                body(interpreter, paren, arguments).map_err(|e| match e {
+                    InterpretError::Conversion { error } => error.at(paren),
+                    other => other,
+                })
+            }
             Function::User {
                 params,
                 body,
                 closure,
                 ..
             } => {
-                let environment =
-                    Rc::new(RefCell::new(Environment::from(closure)));
+                let environment = interpreter.new_environment(closure);
                 for (param, argument) in params.iter().zip(arguments.iter()) {
                     environment
                         .borrow_mut()
@@ -87,10 +240,34 @@ impl Function {
         }
     }
 
-    pub fn arity(&self) -> usize {
+    // Functions compare by identity: the same declaration closed over the
+    // same environment, or the same native body.
+    pub fn is_same(&self, other: &Function) -> bool {
+        match (self, other) {
+            (
+                Function::Native { body: left, .. },
+                Function::Native { body: right, .. },
+            ) => Shared::ptr_eq(left, right),
+            (
+                Function::User {
+                    name: left,
+                    closure: left_closure,
+                    ..
+                },
+                Function::User {
+                    name: right,
+                    closure: right_closure,
+                    ..
+                },
+            ) => left == right && Shared::ptr_eq(left_closure, right_closure),
+            _ => false,
+        }
+    }
+
+    pub fn arity(&self) -> Option<usize> {
         match self {
             Function::Native { arity, .. } => *arity,
-            Function::User { params, .. } => params.len(),
+            Function::User { params, .. } => Some(params.len()),
         }
     }
 }
@@ -112,3 +289,65 @@ impl fmt::Display for Function {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_zero() {
+        assert_eq!(Object::Number(-0.0).to_string(), "-0");
+        assert_eq!(Object::Number(0.0).to_string(), "0");
+        assert_eq!(Object::Number(1.5).to_string(), "1.5");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert!(Object::from(1.5).equals(&Object::Number(1.5)));
+        assert!(Object::from(true).equals(&Object::Boolean(true)));
+        assert!(Object::from("a").equals(&Object::String("a".to_string())));
+        assert!(Object::from("b".to_string())
+            .equals(&Object::String("b".to_string())));
+
+        assert_eq!(f64::try_from(Object::Number(2.0)), Ok(2.0));
+        assert_eq!(bool::try_from(Object::Boolean(false)), Ok(false));
+        assert_eq!(String::try_from(Object::from("c")), Ok("c".to_string()));
+        let error = f64::try_from(Object::Nil).unwrap_err();
+        assert_eq!(
+            error,
+            ConversionError {
+                expected: "number",
+                found: "nil"
+            }
+        );
+        assert!(bool::try_from(Object::Number(0.0)).is_err());
+        assert!(String::try_from(Object::Boolean(true)).is_err());
+
+        let paren = Token::new(crate::token::TokenType::RightParen, ")", 3, 9);
+        assert!(matches!(
+            error.at(&paren),
+            InterpretError::TypeError { token, message }
+                if token.line == 3 && message == "Expected a number but got a nil."
+        ));
+    }
+
+    #[test]
+    fn test_number_display() {
+        let cases = [
+            (NumberDisplay::Auto, "100000000000000000000", "0.0001"),
+            (NumberDisplay::Fixed, "100000000000000000000", "0.0001"),
+            (NumberDisplay::Scientific, "1e20", "1e-4"),
+        ];
+        for (display, large, small) in cases {
+            assert_eq!(format_number(1e20, display), large);
+            assert_eq!(format_number(0.0001, display), small);
+        }
+        assert_eq!(
+            format_number(1e21, NumberDisplay::Auto),
+            "1000000000000000000000"
+        );
+        assert_eq!(format_number(1e-7, NumberDisplay::Auto), "0.0000001");
+        assert_eq!(format_number(1e-7, NumberDisplay::Fixed), "0.0000001");
+        assert_eq!(format_number(-0.0, NumberDisplay::Scientific), "-0");
+    }
+}
@@ -21,6 +21,8 @@ macro_rules! matche_types {
 pub enum ParseError {
     UnexpectedToken { token: Token, message: String },
     InvalidAssignment { token: Token, message: String },
+    MissingSemicolon { token: Token },
+    ExpectedBlock { token: Token, keyword: String },
 }
 
 impl fmt::Display for ParseError {
@@ -45,48 +47,214 @@ impl fmt::Display for ParseError {
                 "Invalid assignment (line {} at {}) {}",
                 token.line, token.lexeme, message
             ),
+            Self::MissingSemicolon { token } => write!(
+                f,
+                "Missing semicolon (line {} after {}) Add ';' after '{}'.",
+                token.line, token.lexeme, token.lexeme
+            ),
+            Self::ExpectedBlock { token, keyword } => write!(
+                f,
+                "Expected block (line {} at {}) Wrap the '{}' body in braces.",
+                token.line, token.lexeme, keyword
+            ),
         }
     }
 }
 
 impl Error for ParseError {}
 
+impl ParseError {
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::UnexpectedToken { token, .. }
+            | Self::InvalidAssignment { token, .. }
+            | Self::MissingSemicolon { token }
+            | Self::ExpectedBlock { token, .. } => token,
+        }
+    }
+}
+
+// The source text the tokens were lexed from, as written, with its lines
+// joined by a space.
+fn source_slice(source: &str, tokens: &[Token]) -> Option<String> {
+    let (first, last) = (tokens.first()?, tokens.last()?);
+    let start = offset(source, first.source_line(), first.column)?;
+    let end_line = last.source_line() + (last.end_line - last.line);
+    let end = offset(source, end_line, last.end_column)?;
+    let text = source.get(start..end)?;
+    Some(text.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+}
+
+// The byte offset of a line and column, counted from 1 as the lexer
+// counts them.
+fn offset(source: &str, line: usize, column: usize) -> Option<usize> {
+    let start: usize = source
+        .split('\n')
+        .take(line.checked_sub(1)?)
+        .map(|line| line.len() + 1)
+        .sum();
+    let rest = source.get(start..)?;
+    rest.char_indices()
+        .map(|(i, _)| start + i)
+        .chain(std::iter::once(source.len()))
+        .nth(column.checked_sub(1)?)
+}
+
+// Spells tokens back out for a parser without the source, keeping the
+// spacing between tokens on a line and joining lines with a space. Any
+// escapes come out as the characters they stand for.
+fn source_text(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    let mut end: Option<(usize, usize)> = None;
+    for token in tokens {
+        let spelling = match token.r#type {
+            TokenType::String { .. } => format!("\"{}\"", token.lexeme),
+            TokenType::Interpolation => format!("\"{}${{", token.lexeme),
+            TokenType::InterpolationMiddle => {
+                format!("}}{}${{", token.lexeme)
+            }
+            TokenType::InterpolationEnd => format!("}}{}\"", token.lexeme),
+            _ => token.lexeme.clone(),
+        };
+        match end {
+            Some((line, column)) if line == token.line => {
+                let gap = token.column.saturating_sub(column);
+                text.push_str(&" ".repeat(gap));
+            }
+            Some(_) => text.push(' '),
+            None => {}
+        }
+        end = Some((token.line, token.column + spelling.chars().count()));
+        text.push_str(&spelling);
+    }
+    text
+}
+
 pub type Result<T> = result::Result<T, ParseError>;
 
 pub struct Parser<'a> {
     current: usize,
-    tokens: &'a Vec<Token>,
+    tokens: &'a [Token],
+    source: Option<&'a str>,
+    strict_braces: bool,
+    loop_bindings: bool,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { current: 0, tokens }
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            current: 0,
+            tokens,
+            source: None,
+            strict_braces: false,
+            loop_bindings: false,
+        }
+    }
+
+    // Keeps the source the tokens came from, so an `assert` can quote its
+    // condition as written.
+    pub fn with_source(tokens: &'a [Token], source: &'a str) -> Self {
+        Self {
+            source: Some(source),
+            ..Self::new(tokens)
+        }
+    }
+
+    // Requires `if`, `else`, `while` and `for` bodies to be blocks, except
+    // for the `if` of an `else if` chain.
+    pub fn strict_braces(mut self, on: bool) -> Self {
+        self.strict_braces = on;
+        self
+    }
+
+    // Gives each iteration of a `for (var ...)` loop its own copy of the
+    // loop variable, so closures created in the body keep that
+    // iteration's value.
+    pub fn loop_bindings(mut self, on: bool) -> Self {
+        self.loop_bindings = on;
+        self
     }
 
     pub fn parse(&mut self) -> Result<Vec<Stmt>> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            statements.push(self.top_level_declaration()?);
         }
         Ok(statements)
     }
 
+    // Parses input that must be exactly one expression.
+    pub fn parse_expression(&mut self) -> Result<Expr> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(ParseError::UnexpectedToken {
+                token: self.peek().clone(),
+                message: "Expect end of expression.".to_string(),
+            });
+        }
+        Ok(expr)
+    }
+
+    // Parses as much as possible, skipping to the next statement after each
+    // error so they can all be reported together.
+    pub fn parse_recovering(&mut self) -> (Vec<Stmt>, Vec<ParseError>) {
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
+        while !self.is_at_end() {
+            match self.top_level_declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize();
+                    statements.push(Stmt::Nil);
+                }
+            }
+        }
+        (statements, errors)
+    }
+
+    // Tests can only be declared here, where the test runner looks for
+    // them.
+    fn top_level_declaration(&mut self) -> Result<Stmt> {
+        if matche_types!(self, TokenType::Test) {
+            self.test_declaration()
+        } else {
+            self.declaration()
+        }
+    }
+
     fn declaration(&mut self) -> Result<Stmt> {
-        let statement = if matche_types!(self, TokenType::Fun) {
+        if matche_types!(self, TokenType::Fun) {
             self.function("function")
         } else if matche_types!(self, TokenType::Var) {
             self.var_declaration()
+        } else if matche_types!(self, TokenType::Const) {
+            self.const_declaration()
+        } else if self.check(TokenType::Test) {
+            Err(ParseError::UnexpectedToken {
+                token: self.peek().clone(),
+                message: "Tests must be declared at the top level.".to_string(),
+            })
         } else {
             self.statement()
+        }
+    }
+
+    fn test_declaration(&mut self) -> Result<Stmt> {
+        let name = match self.peek().r#type {
+            TokenType::String { .. } => self.advance().clone(),
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    token: self.peek().clone(),
+                    message: "Expect test name string.".to_string(),
+                })
+            }
         };
-        statement
-        // match statement {
-        //     Err(_) => {
-        //         self.synchronize();
-        //         Ok(Stmt::Nil)
-        //     }
-        //     other => other,
-        // }
+        let opening = self
+            .consume(TokenType::LeftBrace, "Expect '{' before test body.")?
+            .clone();
+        let body = self.block(&opening)?;
+        Ok(Stmt::Test { name, body })
     }
 
     fn function(&mut self, kind: &str) -> Result<Stmt> {
@@ -117,28 +285,59 @@ impl<'a> Parser<'a> {
             }
         }
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
-        self.consume(
-            TokenType::LeftBrace,
-            format!("Expect '{{' before {} body.", kind).as_str(),
-        )?;
-        let body = self.block()?;
-        Ok(Stmt::Function { name, params, body })
+        let opening = self
+            .consume(
+                TokenType::LeftBrace,
+                format!("Expect '{{' before {} body.", kind).as_str(),
+            )?
+            .clone();
+        let body = self.block(&opening)?;
+        Ok(Stmt::Function {
+            name,
+            params: params.into(),
+            body: body.into(),
+        })
     }
 
     fn var_declaration(&mut self) -> Result<Stmt> {
         let name = self
             .consume(TokenType::Identifier, "Expect variable name.")?
             .clone();
+        let annotation = if matche_types!(self, TokenType::Colon) {
+            Some(
+                self.consume(
+                    TokenType::Identifier,
+                    "Expect type name after ':'.",
+                )?
+                .clone(),
+            )
+        } else {
+            None
+        };
         let initializer = if matche_types!(self, TokenType::Equal) {
             Some(self.expression()?)
         } else {
             None
         };
+        self.consume_semicolon()?;
+        Ok(Stmt::Var {
+            name,
+            annotation,
+            initializer,
+        })
+    }
+
+    fn const_declaration(&mut self) -> Result<Stmt> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect constant name.")?
+            .clone();
         self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after variable declaration.",
+            TokenType::Equal,
+            "Expect '=' after constant name, a constant needs a value.",
         )?;
-        Ok(Stmt::Var { name, initializer })
+        let initializer = self.expression()?;
+        self.consume_semicolon()?;
+        Ok(Stmt::Const { name, initializer })
     }
 
     fn statement(&mut self) -> Result<Stmt> {
@@ -152,9 +351,19 @@ impl<'a> Parser<'a> {
             self.if_statement()
         } else if matche_types!(self, TokenType::Print) {
             self.print_statement()
+        } else if matche_types!(self, TokenType::Assert) {
+            self.assert_statement()
+        } else if matche_types!(self, TokenType::Debug) {
+            let opening = self
+                .consume(TokenType::LeftBrace, "Expect '{' after 'debug'.")?
+                .clone();
+            Ok(Stmt::Debug {
+                statements: self.block(&opening)?,
+            })
         } else if matche_types!(self, TokenType::LeftBrace) {
+            let opening = self.previous().clone();
             Ok(Stmt::Block {
-                statements: self.block()?,
+                statements: self.block(&opening)?,
             })
         } else {
             self.expression_statement()
@@ -173,6 +382,7 @@ impl<'a> Parser<'a> {
     }
 
     fn for_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
         let initializer = if matche_types!(self, TokenType::Semicolon) {
@@ -200,7 +410,12 @@ impl<'a> Parser<'a> {
 
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
+        let mut body = self.body("for")?;
+        if let (true, Some(Stmt::Var { name, .. })) =
+            (self.loop_bindings, &initializer)
+        {
+            body = Self::fresh_binding(&keyword, name, body);
+        }
         if let Some(increment) = increment {
             body = Stmt::Block {
                 statements: vec![
@@ -212,6 +427,7 @@ impl<'a> Parser<'a> {
             }
         }
         body = Stmt::While {
+            keyword,
             condition,
             body: Box::new(body),
         };
@@ -224,12 +440,58 @@ impl<'a> Parser<'a> {
         Ok(body)
     }
 
+    // Wraps the body so it runs against a new binding of the loop
+    // variable, copied in from the loop's binding before the body and
+    // back out after it:
+    //
+    //     var <copy> = i;
+    //     { var i = <copy>; body; <copy> = i; }
+    //     i = <copy>;
+    //
+    // The copy's name can't be written in source. The resolver records
+    // depths per token position, so references at different depths are
+    // placed at the `for` keyword or the variable's declaration.
+    fn fresh_binding(keyword: &Token, name: &Token, body: Stmt) -> Stmt {
+        let at = |lexeme: &str, token: &Token| {
+            Token::new(TokenType::Identifier, lexeme, token.line, token.column)
+        };
+        let copy = format!("for {}", name.lexeme);
+        let (outer, inner) = (at(&name.lexeme, keyword), name.clone());
+        let (copy_outer, copy_inner) = (at(&copy, keyword), at(&copy, name));
+        let assign = |name: Token, value: Token| Stmt::Expression {
+            expression: Expr::Assign {
+                name,
+                value: Box::new(Expr::Variable { name: value }),
+            },
+        };
+        let var = |name: Token, value: Token| Stmt::Var {
+            name,
+            annotation: None,
+            initializer: Some(Expr::Variable { name: value }),
+        };
+        Stmt::Block {
+            statements: vec![
+                var(copy_outer.clone(), outer.clone()),
+                Stmt::Block {
+                    statements: vec![
+                        var(inner.clone(), copy_inner.clone()),
+                        body,
+                        assign(copy_inner, inner),
+                    ],
+                },
+                assign(outer, copy_outer),
+            ],
+        }
+    }
+
     fn while_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
-        let body = self.statement()?;
+        let body = self.body("while")?;
         Ok(Stmt::While {
+            keyword,
             condition,
             body: Box::new(body),
         })
@@ -240,9 +502,13 @@ impl<'a> Parser<'a> {
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
 
-        let then_branch = Box::new(self.statement()?);
+        let then_branch = Box::new(self.body("if")?);
         let else_branch = if matche_types!(self, TokenType::Eles) {
-            Box::new(Some(self.statement()?))
+            if self.check(TokenType::If) {
+                Box::new(Some(self.statement()?))
+            } else {
+                Box::new(Some(self.body("else")?))
+            }
         } else {
             Box::new(None)
         };
@@ -253,24 +519,60 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn body(&mut self, keyword: &str) -> Result<Stmt> {
+        if self.strict_braces && !self.check(TokenType::LeftBrace) {
+            return Err(ParseError::ExpectedBlock {
+                token: self.peek().clone(),
+                keyword: keyword.to_string(),
+            });
+        }
+        self.statement()
+    }
+
     fn print_statement(&mut self) -> Result<Stmt> {
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        self.consume_semicolon()?;
         Ok(Stmt::Print { expression: value })
     }
 
-    fn block(&mut self) -> Result<Vec<Stmt>> {
+    fn assert_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'assert'.")?;
+        let start = self.current;
+        let condition = self.expression()?;
+        let tokens = &self.tokens[start..self.current];
+        let text = self
+            .source
+            .and_then(|source| source_slice(source, tokens))
+            .unwrap_or_else(|| source_text(tokens));
+        self.consume(TokenType::RightParen, "Expect ')' after assertion.")?;
+        self.consume_semicolon()?;
+        Ok(Stmt::Assert {
+            keyword,
+            condition,
+            text,
+        })
+    }
+
+    fn block(&mut self, opening: &Token) -> Result<Vec<Stmt>> {
         let mut statements = Vec::new();
-        while !self.check(TokenType::RightBrace) {
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
             statements.push(self.declaration()?);
         }
-        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        self.consume(
+            TokenType::RightBrace,
+            format!(
+                "Expect '}}' after block, unclosed block opened at line {}.",
+                opening.line
+            )
+            .as_str(),
+        )?;
         Ok(statements)
     }
 
     fn expression_statement(&mut self) -> Result<Stmt> {
         let value = self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        self.consume_semicolon()?;
         Ok(Stmt::Expression { expression: value })
     }
 
@@ -280,15 +582,21 @@ impl<'a> Parser<'a> {
 
     fn assignment(&mut self) -> Result<Expr> {
         let expr = self.or()?;
-        if matche_types!(self, TokenType::Equal) {
+        if matche_types!(
+            self,
+            TokenType::Equal,
+            TokenType::QuestionQuestionEqual
+        ) {
             let equals = self.previous().clone();
-            let value = self.assignment()?;
+            let value = Box::new(self.assignment()?);
 
             return match expr {
-                Expr::Variable { name } => Ok(Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                }),
+                Expr::Variable { name }
+                    if equals.r#type == TokenType::QuestionQuestionEqual =>
+                {
+                    Ok(Expr::DefaultAssign { name, value })
+                }
+                Expr::Variable { name } => Ok(Expr::Assign { name, value }),
                 _ => Err(ParseError::InvalidAssignment {
                     token: equals,
                     message: "Invalid assignment target.".to_string(),
@@ -347,9 +655,23 @@ impl<'a> Parser<'a> {
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
-            TokenType::LessEqual
+            TokenType::LessEqual,
+            TokenType::Is
         ) {
             let operator = self.previous().clone();
+            if operator.r#type == TokenType::Is {
+                let type_name = self
+                    .consume(
+                        TokenType::Identifier,
+                        "Expect type name after 'is'.",
+                    )?
+                    .clone();
+                expr = Expr::Is {
+                    value: Box::new(expr),
+                    type_name,
+                };
+                continue;
+            }
             let right = self.term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
@@ -404,12 +726,13 @@ impl<'a> Parser<'a> {
     fn call(&mut self) -> Result<Expr> {
         let mut expr = self.primary()?;
         while matche_types!(self, TokenType::LeftParen) {
-            expr = self.finish_call(expr)?;
+            let opening = self.previous().clone();
+            expr = self.finish_call(expr, &opening)?;
         }
         Ok(expr)
     }
 
-    fn finish_call(&mut self, callee: Expr) -> Result<Expr> {
+    fn finish_call(&mut self, callee: Expr, opening: &Token) -> Result<Expr> {
         let mut arguments = Vec::new();
         if !self.check(TokenType::RightParen) {
             loop {
@@ -419,8 +742,14 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        let parent =
-            self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+        let parent = self.consume(
+            TokenType::RightParen,
+            format!(
+                "Expect ')' after arguments, unclosed '(' opened at line {}.",
+                opening.line
+            )
+            .as_str(),
+        )?;
         Ok(Expr::Call {
             callee: Box::new(callee),
             paren: parent.clone(),
@@ -428,6 +757,57 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn interpolation(&mut self) -> Result<Expr> {
+        let mut parts = Vec::new();
+        loop {
+            parts.push(Expr::Literal {
+                value: LiteralValue::String(self.previous().lexeme.clone()),
+            });
+            parts.push(self.expression()?);
+            match &self.peek().r#type {
+                TokenType::InterpolationMiddle => {
+                    self.advance();
+                }
+                TokenType::InterpolationEnd => {
+                    let literal = self.advance().lexeme.clone();
+                    parts.push(Expr::Literal {
+                        value: LiteralValue::String(literal),
+                    });
+                    return Ok(Expr::Interpolation { parts });
+                }
+                _ => {
+                    return Err(ParseError::UnexpectedToken {
+                        token: self.peek().clone(),
+                        message: "Expect '}' after interpolated expression."
+                            .to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    fn when(&mut self) -> Result<Expr> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'when'.")?;
+        let mut arms = Vec::new();
+        let mut else_arm = None;
+        while !self.check(TokenType::RightBrace) {
+            if matche_types!(self, TokenType::Eles) {
+                self.consume(TokenType::Colon, "Expect ':' after 'else'.")?;
+                else_arm = Some(Box::new(self.expression()?));
+                matche_types!(self, TokenType::Comma);
+                break;
+            }
+            let condition = self.expression()?;
+            self.consume(TokenType::Colon, "Expect ':' after condition.")?;
+            arms.push((condition, self.expression()?));
+            if !matche_types!(self, TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after 'when' arms.")?;
+        Ok(Expr::When { arms, else_arm })
+    }
+
     fn primary(&mut self) -> Result<Expr> {
         let expr = match &self.peek().r#type {
             TokenType::False => {
@@ -455,6 +835,14 @@ impl<'a> Parser<'a> {
                     value: LiteralValue::String(literal),
                 }
             }
+            TokenType::Interpolation => {
+                self.advance();
+                self.interpolation()?
+            }
+            TokenType::When => {
+                self.advance();
+                self.when()?
+            }
             TokenType::Number { literal } => {
                 let literal = *literal;
                 self.advance();
@@ -463,11 +851,16 @@ impl<'a> Parser<'a> {
                 }
             }
             TokenType::LeftParen => {
-                self.advance();
+                let opening = self.advance().clone();
                 let expr = self.expression()?;
                 self.consume(
                     TokenType::RightParen,
-                    "Expect ')' after expression.",
+                    format!(
+                        "Expect ')' after expression, unclosed '(' opened at \
+                         line {}.",
+                        opening.line
+                    )
+                    .as_str(),
                 )?;
                 Expr::Grouping {
                     expression: Box::new(expr),
@@ -480,9 +873,8 @@ impl<'a> Parser<'a> {
                 }
             }
             _ => {
-                self.advance();
                 return Err(ParseError::UnexpectedToken {
-                    token: self.previous().clone(),
+                    token: self.peek().clone(),
                     message: "Expect expression.".to_string(),
                 });
             }
@@ -500,26 +892,39 @@ impl<'a> Parser<'a> {
         })
     }
 
-    // fn synchronize(&mut self) {
-    //     self.advance();
-    //     while !self.is_at_end() {
-    //         if self.previous().r#type == TokenType::Semicolon {
-    //             return;
-    //         }
-    //         match self.peek().r#type {
-    //             TokenType::Class
-    //             | TokenType::Fun
-    //             | TokenType::Var
-    //             | TokenType::For
-    //             | TokenType::If
-    //             | TokenType::While
-    //             | TokenType::Print
-    //             | TokenType::Return => return,
-    //             _ => {}
-    //         }
-    //         self.advance();
-    //     }
-    // }
+    fn consume_semicolon(&mut self) -> Result<&Token> {
+        if self.check(TokenType::Semicolon) {
+            return Ok(self.advance());
+        }
+        Err(ParseError::MissingSemicolon {
+            token: self.previous().clone(),
+        })
+    }
+
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().r#type == TokenType::Semicolon {
+                return;
+            }
+            match self.peek().r#type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::Const
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Assert
+                | TokenType::Debug
+                | TokenType::Test
+                | TokenType::Return => return,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
 
     fn check(&self, r#type: TokenType) -> bool {
         if self.is_at_end() {
@@ -551,3 +956,134 @@ impl<'a> Parser<'a> {
             .expect("Previous was empty.")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> Result<Vec<Stmt>> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan().unwrap();
+        Parser::new(tokens).parse()
+    }
+
+    fn parse_error(source: &str) -> String {
+        match parse(source) {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_unclosed_paren_reports_opening_line() {
+        let message = parse_error("var a = (1 +\n  2\n  * 3;");
+        assert!(message.contains("line 3 at ;"), "{}", message);
+        assert!(message.contains("opened at line 1"), "{}", message);
+        let message = parse_error("print 1;\nclock(\n  1, 2;");
+        assert!(message.contains("after arguments"), "{}", message);
+        assert!(message.contains("opened at line 2"), "{}", message);
+    }
+
+    #[test]
+    fn test_unclosed_block_reports_opening_line() {
+        let source = "var a = 1;\nfun f() {\n  print a;\n\nprint 2;\n";
+        let message = parse_error(source);
+        assert!(message.contains("at end"), "{}", message);
+        assert!(message.contains("opened at line 2"), "{}", message);
+    }
+
+    #[test]
+    fn test_missing_semicolon_after_expression() {
+        match parse("print 1\nprint 2;") {
+            Err(ParseError::MissingSemicolon { token }) => {
+                assert_eq!(token.lexeme, "1");
+                assert_eq!(token.line, 1);
+            }
+            _ => panic!("Expected a missing semicolon error"),
+        }
+        let message = parse_error("x = 1 + 2");
+        assert!(message.contains("Add ';' after '2'"), "{}", message);
+    }
+
+    #[test]
+    fn test_missing_semicolon_after_var() {
+        match parse("var a = \"x\"\nvar b;") {
+            Err(ParseError::MissingSemicolon { token }) => {
+                assert_eq!(token.lexeme, "x");
+                assert_eq!(token.line, 1);
+            }
+            _ => panic!("Expected a missing semicolon error"),
+        }
+    }
+
+    #[test]
+    fn test_unclosed_interpolation() {
+        let message = parse_error("print \"${a b}\";");
+        assert!(message.contains("at b"), "{}", message);
+        assert!(message.contains("Expect '}'"), "{}", message);
+        let message = parse_error("print \"${\"a\" \"b\"}\";");
+        assert!(message.contains("at b"), "{}", message);
+        assert!(
+            message.contains("Expect '}' after interpolated expression."),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_strict_braces() {
+        let strict = |source: &str| {
+            let mut lexer = Lexer::new(source);
+            let tokens = lexer.scan().unwrap();
+            Parser::new(tokens).strict_braces(true).parse().map(|_| ())
+        };
+        assert!(matches!(
+            strict("var x; if (true) x = 1;"),
+            Err(ParseError::ExpectedBlock { token, keyword })
+                if token.lexeme == "x" && keyword == "if"
+        ));
+        assert!(strict("var x; if (true) { x = 1; }").is_ok());
+        assert!(strict("if (true) {} else print 1;").is_err());
+        assert!(strict("while (false) print 1;").is_err());
+        assert!(strict("for (;false;) print 1;").is_err());
+        assert!(strict("if (true) {} else if (false) {} else {}").is_ok());
+        assert!(parse("var x; if (true) x = 1;").is_ok());
+
+        let tokens = Lexer::new("for (var i = 0; i < 1; i = i + 1) print i;")
+            .scan()
+            .unwrap()
+            .to_vec();
+        let mut both =
+            Parser::new(&tokens).strict_braces(true).loop_bindings(true);
+        assert!(matches!(
+            both.parse(),
+            Err(ParseError::ExpectedBlock { keyword, .. }) if keyword == "for"
+        ));
+    }
+
+    #[test]
+    fn test_const_requires_initializer() {
+        assert!(parse("const PI = 3.14;").is_ok());
+        let message = parse_error("const PI;");
+        assert!(message.contains("a constant needs a value"), "{}", message);
+    }
+
+    #[test]
+    fn test_default_assign_target() {
+        assert!(parse("var x; x ??= 1;").is_ok());
+        let message = parse_error("var x; (x) ??= 1;");
+        assert!(message.contains("Invalid assignment target"), "{}", message);
+    }
+
+    #[test]
+    fn test_nested_test_is_rejected() {
+        assert!(parse("test \"top\" { assert(true); }").is_ok());
+        let message = parse_error("{ test \"x\" { assert(false); } }");
+        assert!(message.contains("at the top level"), "{}", message);
+        let message = parse_error("fun f() { test \"x\" {} }");
+        assert!(message.contains("at the top level"), "{}", message);
+        let message = parse_error("test \"a\" { test \"b\" {} }");
+        assert!(message.contains("at the top level"), "{}", message);
+    }
+}
@@ -1,8 +1,13 @@
-use std::{collections::HashMap, error::Error, fmt, result};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt, result,
+};
 
 use crate::{
-    ast::{expr, stmt, Expr, Stmt},
+    ast::{expr, stmt, Expr, LiteralValue, Stmt},
     interpreter::Interpreter,
+    shared::Shared,
     token::Token,
 };
 
@@ -11,6 +16,7 @@ pub enum ResolveError {
     AlreadyDeclared { token: Token },
     ReadInOwnInitializer { token: Token },
     TopLevelReturn { token: Token },
+    AssignToConst { token: Token },
 }
 
 impl fmt::Display for ResolveError {
@@ -31,40 +37,169 @@ impl fmt::Display for ResolveError {
                 "Cannot return from top-level code (line {} at {}).",
                 token.line, token.lexeme,
             ),
+            Self::AssignToConst { token } => write!(
+                f,
+                "Cannot assign to constant '{}' (line {} at {}).",
+                token.lexeme, token.line, token.lexeme,
+            ),
         }
     }
 }
 
 impl Error for ResolveError {}
 
+impl ResolveError {
+    pub fn token(&self) -> &Token {
+        match self {
+            Self::AlreadyDeclared { token }
+            | Self::ReadInOwnInitializer { token }
+            | Self::TopLevelReturn { token }
+            | Self::AssignToConst { token } => token,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, ResolveError>;
 
+#[derive(Debug)]
+pub enum ResolveWarning {
+    PrintingCallable { token: Token },
+    ShadowsNative { token: Token },
+    InfiniteLoop { token: Token },
+}
+
+impl fmt::Display for ResolveWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrintingCallable { token } => write!(
+                f,
+                "Printing function '{}' without calling it, did you mean '{}()'? (line {} at {}).",
+                token.lexeme, token.lexeme, token.line, token.lexeme,
+            ),
+            Self::ShadowsNative { token } => write!(
+                f,
+                "Declaration of '{}' replaces the native function of the same name (line {} at {}).",
+                token.lexeme, token.line, token.lexeme,
+            ),
+            Self::InfiniteLoop { token } => write!(
+                f,
+                "Loop condition is always true and the body never returns (line {} at {}).",
+                token.line, token.lexeme,
+            ),
+        }
+    }
+}
+
+// Whether a `return` can run somewhere in the statement. Returns inside
+// a nested function leave that function, not the enclosing loop. There is
+// no `break`, so this is the only way out of a loop.
+fn returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return { .. } => true,
+        Stmt::Block { statements } | Stmt::Debug { statements } => {
+            statements.iter().any(returns)
+        }
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => returns(then_branch) || else_branch.iter().any(returns),
+        Stmt::While { body, .. } => returns(body),
+        _ => false,
+    }
+}
+
+// The first top-level function of each name is defined before anything
+// else runs, so a variable declared ahead of it would be left holding the
+// variable's value. That order is rejected instead.
+fn check_hoisting(statements: &[Stmt]) -> Result<()> {
+    let mut variables = HashSet::new();
+    let mut functions = HashSet::new();
+    for statement in statements {
+        match statement {
+            Stmt::Var { name, .. } | Stmt::Const { name, .. } => {
+                variables.insert(name.lexeme.as_str());
+            }
+            Stmt::Function { name, .. }
+                if functions.insert(name.lexeme.as_str())
+                    && variables.contains(name.lexeme.as_str()) =>
+            {
+                return Err(ResolveError::AlreadyDeclared {
+                    token: name.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 enum FunctionType {
     None,
     Function,
 }
 
+struct Binding {
+    defined: bool,
+    constant: bool,
+    declaration: Token,
+}
+
 pub struct Resolver<'i> {
     interpreter: &'i mut Interpreter,
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, Binding>>,
     current_function: FunctionType,
+    lints: bool,
+    top_level_return: bool,
+    // Names bound to functions, one set for the globals followed by one
+    // per entry in `scopes`.
+    callables: Vec<HashSet<String>>,
+    natives: HashSet<String>,
+    pub warnings: Vec<ResolveWarning>,
 }
 
 impl<'i> Resolver<'i> {
     pub fn new(interpreter: &'i mut Interpreter) -> Self {
+        let globals = interpreter.callable_globals();
+        let natives = interpreter.native_globals();
         Resolver {
             interpreter,
             scopes: Vec::new(),
             current_function: FunctionType::None,
+            lints: false,
+            top_level_return: false,
+            callables: vec![globals],
+            natives,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn with_lints(interpreter: &'i mut Interpreter) -> Self {
+        Resolver {
+            lints: true,
+            ..Self::new(interpreter)
+        }
+    }
+
+    // The REPL lets a top-level `return` through so it can display the
+    // returned value instead of failing.
+    pub fn for_repl(interpreter: &'i mut Interpreter) -> Self {
+        Resolver {
+            top_level_return: true,
+            ..Self::new(interpreter)
         }
     }
 
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.callables.push(HashSet::new());
     }
 
-    pub fn resolve_stmts(&mut self, statements: &Vec<Stmt>) -> Result<()> {
+    pub fn resolve_stmts(&mut self, statements: &[Stmt]) -> Result<()> {
+        if self.scopes.is_empty() {
+            check_hoisting(statements)?;
+        }
         for statement in statements {
             self.resolve_stmt(statement)?;
         }
@@ -77,6 +212,28 @@ impl<'i> Resolver<'i> {
 
     fn end_scope(&mut self) {
         self.scopes.pop();
+        self.callables.pop();
+    }
+
+    fn mark_callable(&mut self, name: &Token, callable: bool) {
+        if let Some(callables) = self.callables.last_mut() {
+            if callable {
+                callables.insert(name.lexeme.clone());
+            } else {
+                callables.remove(&name.lexeme);
+            }
+        }
+    }
+
+    fn is_callable(&self, name: &Token) -> bool {
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme))
+            .map(|i| self.scopes.len() - i)
+            .unwrap_or(0);
+        self.callables[depth].contains(&name.lexeme)
     }
 
     fn declare(&mut self, name: &Token) -> Result<()> {
@@ -86,34 +243,74 @@ impl<'i> Resolver<'i> {
                     token: name.clone(),
                 });
             }
-            scope.insert(name.lexeme.clone(), false);
+            scope.insert(
+                name.lexeme.clone(),
+                Binding {
+                    defined: false,
+                    constant: false,
+                    declaration: name.clone(),
+                },
+            );
         }
         Ok(())
     }
 
     fn define(&mut self, name: &Token) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), true);
+        self.define_binding(name, false);
+    }
+
+    fn define_binding(&mut self, name: &Token, constant: bool) {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(
+                    name.lexeme.clone(),
+                    Binding {
+                        defined: true,
+                        constant,
+                        declaration: name.clone(),
+                    },
+                );
+            }
+            None => {
+                if self.lints && self.natives.contains(&name.lexeme) {
+                    self.warnings.push(ResolveWarning::ShadowsNative {
+                        token: name.clone(),
+                    });
+                }
+                self.interpreter.mark_constant(&name.lexeme, constant);
+            }
         }
     }
 
-    fn resolve_expr(&mut self, expression: &Expr) -> Result<()> {
+    fn is_constant(&self, name: &Token) -> bool {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+        {
+            Some(binding) => binding.constant,
+            None => self.interpreter.is_constant(&name.lexeme),
+        }
+    }
+
+    pub fn resolve_expr(&mut self, expression: &Expr) -> Result<()> {
         expression.accept(self)
     }
 
     fn resolve_local(&mut self, name: &Token) {
         for (i, scope) in self.scopes.iter().rev().enumerate() {
-            if scope.contains_key(&name.lexeme) {
-                dbg!(i, name);
-                self.interpreter.resolve(name, i);
+            if let Some(binding) = scope.get(&name.lexeme) {
+                self.interpreter.resolve(name, i, &binding.declaration);
+                return;
             }
         }
     }
 
     fn resolve_function(
         &mut self,
-        params: &Vec<Token>,
-        body: &Vec<Stmt>,
+        params: &[Token],
+        body: &[Stmt],
         func_type: FunctionType,
     ) -> Result<()> {
         let enclosing_function = self.current_function.clone();
@@ -135,8 +332,8 @@ impl<'i> Resolver<'i> {
 impl<'i> expr::Visitor<Result<()>> for Resolver<'i> {
     fn visit_variable_expr(&mut self, name: &Token) -> Result<()> {
         if let Some(scope) = self.scopes.last() {
-            if let Some(flag) = scope.get(&name.lexeme) {
-                if *flag == false {
+            if let Some(binding) = scope.get(&name.lexeme) {
+                if !binding.defined {
                     return Err(ResolveError::ReadInOwnInitializer {
                         token: name.clone(),
                     });
@@ -147,14 +344,23 @@ impl<'i> expr::Visitor<Result<()>> for Resolver<'i> {
         Ok(())
     }
 
+    // Walks left-nested chains in a loop, like the interpreter does.
     fn visit_binary_expr(
         &mut self,
         left: &Expr,
         _operator: &Token,
         right: &Expr,
     ) -> Result<()> {
-        self.resolve_expr(left)?;
-        self.resolve_expr(right)?;
+        let mut rights = vec![right];
+        let mut innermost = left;
+        while let Expr::Binary { left, right, .. } = innermost {
+            rights.push(right);
+            innermost = left;
+        }
+        self.resolve_expr(innermost)?;
+        for right in rights.into_iter().rev() {
+            self.resolve_expr(right)?;
+        }
         Ok(())
     }
 
@@ -189,16 +395,29 @@ impl<'i> expr::Visitor<Result<()>> for Resolver<'i> {
     }
 
     fn visit_assign_expr(&mut self, name: &Token, expr: &Expr) -> Result<()> {
+        if self.is_constant(name) {
+            return Err(ResolveError::AssignToConst {
+                token: name.clone(),
+            });
+        }
         self.resolve_expr(expr)?;
         self.resolve_local(name);
         Ok(())
     }
 
+    fn visit_default_assign_expr(
+        &mut self,
+        name: &Token,
+        expr: &Expr,
+    ) -> Result<()> {
+        self.visit_assign_expr(name, expr)
+    }
+
     fn visit_call_expr(
         &mut self,
         callee: &Expr,
         _paren: &Token,
-        arguments: &Vec<Expr>,
+        arguments: &[Expr],
     ) -> Result<()> {
         self.resolve_expr(callee)?;
         for argument in arguments {
@@ -206,19 +425,63 @@ impl<'i> expr::Visitor<Result<()>> for Resolver<'i> {
         }
         Ok(())
     }
+
+    fn visit_is_expr(
+        &mut self,
+        value: &Expr,
+        _type_name: &Token,
+    ) -> Result<()> {
+        self.resolve_expr(value)
+    }
+
+    fn visit_interpolation_expr(&mut self, parts: &[Expr]) -> Result<()> {
+        for part in parts {
+            self.resolve_expr(part)?;
+        }
+        Ok(())
+    }
+
+    fn visit_when_expr(
+        &mut self,
+        arms: &[(Expr, Expr)],
+        else_arm: Option<&Expr>,
+    ) -> Result<()> {
+        for (condition, value) in arms {
+            self.resolve_expr(condition)?;
+            self.resolve_expr(value)?;
+        }
+        if let Some(else_arm) = else_arm {
+            self.resolve_expr(else_arm)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'i> stmt::Visitor<Result<()>> for Resolver<'i> {
-    fn visit_block_stmt(&mut self, statements: &Vec<Stmt>) -> Result<()> {
+    fn visit_block_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
         self.begin_scope();
         self.resolve_stmts(statements)?;
         self.end_scope();
         Ok(())
     }
 
+    fn visit_test_stmt(&mut self, _name: &Token, body: &[Stmt]) -> Result<()> {
+        self.visit_block_stmt(body)
+    }
+
+    fn visit_nil_stmt(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Resolved even when it won't run, so its mistakes still surface.
+    fn visit_debug_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
+        self.visit_block_stmt(statements)
+    }
+
     fn visit_var_stmt(
         &mut self,
         name: &Token,
+        _annotation: Option<&Token>,
         initializer: &Option<Expr>,
     ) -> Result<()> {
         self.declare(name)?;
@@ -226,6 +489,19 @@ impl<'i> stmt::Visitor<Result<()>> for Resolver<'i> {
             self.resolve_expr(init)?;
         }
         self.define(name);
+        self.mark_callable(name, false);
+        Ok(())
+    }
+
+    fn visit_const_stmt(
+        &mut self,
+        name: &Token,
+        initializer: &Expr,
+    ) -> Result<()> {
+        self.declare(name)?;
+        self.resolve_expr(initializer)?;
+        self.define_binding(name, true);
+        self.mark_callable(name, false);
         Ok(())
     }
 
@@ -236,11 +512,12 @@ impl<'i> stmt::Visitor<Result<()>> for Resolver<'i> {
     fn visit_function_stmt(
         &mut self,
         name: &Token,
-        params: &Vec<Token>,
-        body: &Vec<Stmt>,
+        params: &Shared<[Token]>,
+        body: &Shared<[Stmt]>,
     ) -> Result<()> {
         self.declare(name)?;
         self.define(name);
+        self.mark_callable(name, true);
         self.resolve_function(params, body, FunctionType::Function)?;
         Ok(())
     }
@@ -260,15 +537,33 @@ impl<'i> stmt::Visitor<Result<()>> for Resolver<'i> {
     }
 
     fn visit_print_stmt(&mut self, expression: &Expr) -> Result<()> {
+        if let Expr::Variable { name } = expression {
+            if self.lints && self.is_callable(name) {
+                self.warnings.push(ResolveWarning::PrintingCallable {
+                    token: name.clone(),
+                });
+            }
+        }
         self.resolve_expr(expression)
     }
 
+    fn visit_assert_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: &Expr,
+        _text: &str,
+    ) -> Result<()> {
+        self.resolve_expr(condition)
+    }
+
     fn visit_return_stmt(
         &mut self,
         keyword: &Token,
         value: &Option<Expr>,
     ) -> Result<()> {
-        if let FunctionType::None = self.current_function {
+        if matches!(self.current_function, FunctionType::None)
+            && !self.top_level_return
+        {
             return Err(ResolveError::TopLevelReturn {
                 token: keyword.clone(),
             });
@@ -282,11 +577,143 @@ impl<'i> stmt::Visitor<Result<()>> for Resolver<'i> {
 
     fn visit_while_stmt(
         &mut self,
+        keyword: &Token,
         condition: &Expr,
         body: &Stmt,
     ) -> Result<()> {
+        let always = matches!(
+            condition,
+            Expr::Literal {
+                value: LiteralValue::Boolean(true)
+            }
+        );
+        if self.lints && always && !returns(body) {
+            self.warnings.push(ResolveWarning::InfiniteLoop {
+                token: keyword.clone(),
+            });
+        }
         self.resolve_expr(condition)?;
         self.resolve_stmt(body)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn lint(source: &str) -> Vec<ResolveWarning> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        let mut resolver = Resolver::with_lints(&mut interpreter);
+        resolver.resolve_stmts(&statements).unwrap();
+        resolver.warnings
+    }
+
+    #[test]
+    fn test_print_callable_warns() {
+        let warnings = lint("print clock;");
+        assert!(matches!(
+            warnings.as_slice(),
+            [ResolveWarning::PrintingCallable { token }] if token.lexeme == "clock"
+        ));
+        let warnings = lint("{ fun f() {} print f; }");
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_print_call_does_not_warn() {
+        assert!(lint("print clock();").is_empty());
+        assert!(lint("fun f() {} { var f = 1; print f; }").is_empty());
+        assert!(lint("var x = 1; print x;").is_empty());
+    }
+
+    #[test]
+    fn test_shadowing_native_warns() {
+        let warnings = lint("var clock = 1;");
+        assert!(matches!(
+            warnings.as_slice(),
+            [ResolveWarning::ShadowsNative { token }] if token.lexeme == "clock"
+        ));
+        assert_eq!(lint("fun max(a, b) { return a; }").len(), 1);
+        assert!(lint("var clocks = 1;").is_empty());
+        assert!(lint("{ var clock = 1; }").is_empty());
+    }
+
+    #[test]
+    fn test_infinite_loop_warns() {
+        let warnings = lint("while (true) {}");
+        assert!(matches!(
+            warnings.as_slice(),
+            [ResolveWarning::InfiniteLoop { token }] if token.lexeme == "while"
+        ));
+        assert_eq!(lint("for (;;) { print 1; }").len(), 1);
+        assert_eq!(lint("while (true) { fun f() { return; } f(); }").len(), 1);
+    }
+
+    #[test]
+    fn test_loop_with_return_does_not_warn() {
+        assert!(lint("fun f() { while (true) { return; } }").is_empty());
+        assert!(lint("fun f() { for (;;) { if (true) return 1; } }").is_empty());
+        assert!(lint("var x = true; while (x) { x = false; }").is_empty());
+    }
+
+    #[test]
+    fn test_resolution_map_points_at_declaration() {
+        let source = "fun outer() {\n  var count = 0;\n  fun inner() {\n    var other = 1;\n    return count + other;\n  }\n  return inner;\n}";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.scan().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        Resolver::new(&mut interpreter)
+            .resolve_stmts(&statements)
+            .unwrap();
+
+        let map = interpreter.resolution_map();
+        let (reference, declaration) = map
+            .iter()
+            .find(|(reference, _)| reference.lexeme == "count")
+            .unwrap();
+        assert_eq!((reference.line, reference.column), (5, 12));
+        assert_eq!((declaration.line, declaration.column), (2, 7));
+        let (_, declaration) = map
+            .iter()
+            .find(|(reference, _)| reference.lexeme == "other")
+            .unwrap();
+        assert_eq!((declaration.line, declaration.column), (4, 9));
+    }
+
+    #[test]
+    fn test_assign_to_const() {
+        let resolve = |source: &str| {
+            let mut lexer = Lexer::new(source);
+            let tokens = lexer.scan().unwrap();
+            let statements = Parser::new(tokens).parse().unwrap();
+            let mut interpreter = Interpreter::new();
+            Resolver::new(&mut interpreter).resolve_stmts(&statements)
+        };
+        assert!(matches!(
+            resolve("const PI = 3.14;\nPI = 3;"),
+            Err(ResolveError::AssignToConst { token }) if token.line == 2
+        ));
+        assert!(resolve("fun f() { const a = 1; a = 2; }").is_err());
+        assert!(resolve("const a = 1; { var a = 1; a = 2; }").is_ok());
+        assert!(resolve("const a = 1; var a = 2; a = 3;").is_ok());
+    }
+
+    #[test]
+    fn test_debug_block_is_resolved() {
+        let tokens =
+            Lexer::new("debug { var a = a; }").scan().unwrap().to_vec();
+        let statements = Parser::new(&tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.debug = false;
+        assert!(matches!(
+            Resolver::new(&mut interpreter).resolve_stmts(&statements),
+            Err(ResolveError::ReadInOwnInitializer { .. })
+        ));
+    }
+}
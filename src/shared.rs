@@ -0,0 +1,73 @@
+// Pointer and interior-mutability types used for environments and
+// function values. The default build uses `Rc`/`RefCell`; the `sync`
+// feature swaps in `Arc`/`RwLock` so an `Interpreter` is `Send`.
+
+use crate::{
+    interpreter::{InterpretError, Interpreter},
+    object::Object,
+    token::Token,
+};
+
+#[cfg(not(feature = "sync"))]
+pub type Shared<T> = std::rc::Rc<T>;
+
+#[cfg(feature = "sync")]
+pub type Shared<T> = std::sync::Arc<T>;
+
+#[cfg(not(feature = "sync"))]
+pub type Lock<T> = std::cell::RefCell<T>;
+
+#[cfg(feature = "sync")]
+#[derive(Debug)]
+pub struct Lock<T>(std::sync::RwLock<T>);
+
+#[cfg(feature = "sync")]
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock(std::sync::RwLock::new(value))
+    }
+
+    pub fn borrow(&self) -> std::sync::RwLockReadGuard<'_, T> {
+        self.0.read().unwrap()
+    }
+
+    pub fn borrow_mut(&self) -> std::sync::RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap()
+    }
+}
+
+#[cfg(not(feature = "sync"))]
+pub trait NativeBody:
+    Fn(&mut Interpreter, &Token, &[Object]) -> Result<Object, InterpretError>
+{
+}
+
+#[cfg(not(feature = "sync"))]
+impl<F> NativeBody for F where
+    F: Fn(
+        &mut Interpreter,
+        &Token,
+        &[Object],
+    ) -> Result<Object, InterpretError>
+{
+}
+
+#[cfg(feature = "sync")]
+pub trait NativeBody:
+    Fn(&mut Interpreter, &Token, &[Object]) -> Result<Object, InterpretError>
+    + Send
+    + Sync
+{
+}
+
+#[cfg(feature = "sync")]
+impl<F> NativeBody for F where
+    F: Fn(
+            &mut Interpreter,
+            &Token,
+            &[Object],
+        ) -> Result<Object, InterpretError>
+        + Send
+        + Sync
+{
+}
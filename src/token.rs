@@ -1,4 +1,8 @@
-use std::hash::{Hash, Hasher};
+use crate::shared::Shared;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
@@ -11,6 +15,7 @@ pub enum TokenType {
     Minus,
     Plus,
     Semicolon,
+    Colon,
     Slash,
     Star,
 
@@ -23,30 +28,52 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionQuestionEqual,
 
     // Literals.
     Identifier,
-    String { literal: String },
-    Number { literal: f64 },
+    String {
+        literal: String,
+    },
+    // A string segment, kept in the lexeme, that ends at `${`. The embedded
+    // expression's tokens follow and the string resumes after its `}`.
+    Interpolation,
+    // Segments that resume after an embedded expression's `}`: one that
+    // ends at another `${`, and the one that ends the string.
+    InterpolationMiddle,
+    InterpolationEnd,
+    // A `//` comment, kept in the lexeme. Only emitted by a lexer built with
+    // `Lexer::with_comments`.
+    Comment,
+    Number {
+        literal: f64,
+    },
 
     // Keywords.
     And,
+    Assert,
     Class,
+    Const,
+    Debug,
     Eles,
     False,
     Fun,
     For,
     If,
+    Is,
     Nil,
     Or,
     Print,
     Return,
     Super,
+    Test,
     This,
     True,
     Var,
+    When,
     While,
 
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
 }
 
@@ -55,17 +82,85 @@ pub struct Token {
     pub r#type: TokenType,
     pub lexeme: String,
     pub line: usize,
+    pub column: usize,
+    // Position just past the token's last character. Only a string can
+    // end on a later line than it starts.
+    pub end_line: usize,
+    pub end_column: usize,
+    // Set for tokens from the lexer; the parser makes up some without one.
+    pub origin: Option<Shared<Origin>>,
+}
+
+// The source a token was lexed from, and what a `#line` directive before
+// it says about its position.
+pub struct Origin {
+    pub source: Shared<str>,
+    pub file: Option<String>,
+    // The reported line minus the line in the source.
+    pub line_shift: isize,
+}
+
+impl Origin {
+    // Whether a `#line` directive renamed or renumbered the source.
+    pub fn is_renumbered(&self) -> bool {
+        self.file.is_some() || self.line_shift != 0
+    }
+}
+
+// Tokens of one source share its origin, so most comparisons never look
+// at the text.
+impl PartialEq for Origin {
+    fn eq(&self, other: &Self) -> bool {
+        (Shared::ptr_eq(&self.source, &other.source)
+            || self.source == other.source)
+            && self.file == other.file
+            && self.line_shift == other.line_shift
+    }
+}
+
+impl fmt::Debug for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Origin")
+            .field("file", &self.file)
+            .field("line_shift", &self.line_shift)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Token {
-    pub fn new(r#type: TokenType, lexeme: &str, line: usize) -> Self {
+    pub fn new(
+        r#type: TokenType,
+        lexeme: &str,
+        line: usize,
+        column: usize,
+    ) -> Self {
         Self {
             r#type,
             lexeme: lexeme.to_string(),
             line,
+            column,
+            end_line: line,
+            end_column: column + lexeme.chars().count(),
+            origin: None,
+        }
+    }
+
+    // The line the token is on in the source text, before any `#line`
+    // directive renumbers it. Unlike `line`, no two tokens share this and
+    // their column.
+    pub fn source_line(&self) -> usize {
+        match &self.origin {
+            Some(origin) => (self.line as isize - origin.line_shift) as usize,
+            None => self.line,
         }
     }
 
+    pub fn file(&self) -> Option<&str> {
+        self.origin
+            .as_ref()
+            .and_then(|origin| origin.file.as_deref())
+    }
+
     pub fn get_keyword(id: &str) -> Option<TokenType> {
         match id {
             "and" => Some(TokenType::And),
@@ -73,17 +168,23 @@ impl Token {
             "false" => Some(TokenType::False),
             "true" => Some(TokenType::True),
             "if" => Some(TokenType::If),
+            "is" => Some(TokenType::Is),
             "else" => Some(TokenType::Eles),
             "for" => Some(TokenType::For),
+            "when" => Some(TokenType::When),
             "while" => Some(TokenType::While),
             "fun" => Some(TokenType::Fun),
             "return" => Some(TokenType::Return),
             "class" => Some(TokenType::Class),
+            "const" => Some(TokenType::Const),
             "super" => Some(TokenType::Super),
             "this" => Some(TokenType::This),
             "var" => Some(TokenType::Var),
             "nil" => Some(TokenType::Nil),
             "print" => Some(TokenType::Print),
+            "assert" => Some(TokenType::Assert),
+            "debug" => Some(TokenType::Debug),
+            "test" => Some(TokenType::Test),
             _ => None,
         }
     }
@@ -92,7 +193,8 @@ impl Token {
 impl Hash for Token {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.lexeme.hash(state);
-        self.line.hash(state);
+        self.source_line().hash(state);
+        self.column.hash(state);
     }
 }
 
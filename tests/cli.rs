@@ -0,0 +1,132 @@
+use std::{env, fs, process::Command};
+
+fn lox_status(args: &[&str]) -> (Option<i32>, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn lox(args: &[&str]) -> (String, String) {
+    let (_, stdout, stderr) = lox_status(args);
+    (stdout, stderr)
+}
+
+#[test]
+fn test_time_reports_phases() {
+    let (_, stderr) = lox(&["--time", "examples/for.lox"]);
+    for phase in ["lex", "parse", "resolve", "interpret"] {
+        assert!(
+            stderr.lines().any(|line| line.starts_with(phase)),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_no_timings_without_flag() {
+    let (_, stderr) = lox(&["examples/for.lox"]);
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn test_check_clean_file() {
+    let (status, stdout, stderr) = lox_status(&["--check", "examples/for.lox"]);
+    assert_eq!(status, Some(0));
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert!(stderr.is_empty(), "{}", stderr);
+}
+
+#[test]
+fn test_check_broken_file() {
+    let path = env::temp_dir().join("lox-rs-check-broken.lox");
+    fs::write(&path, "var a = 1;\nprint a +;\nvar = 2;\n").unwrap();
+    let (status, stdout, stderr) =
+        lox_status(&["--check", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(status, Some(65));
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert_eq!(stderr.lines().count(), 2, "{}", stderr);
+    assert!(stderr.contains("line 2"), "{}", stderr);
+    assert!(stderr.contains("line 3"), "{}", stderr);
+}
+
+#[test]
+fn test_check_missing_file() {
+    let (status, stdout, stderr) =
+        lox_status(&["--check", "examples/no-such-file.lox"]);
+    assert_eq!(status, Some(66));
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert!(
+        stderr.starts_with("examples/no-such-file.lox: "),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_write_has_no_newline() {
+    let path = env::temp_dir().join("lox-rs-write.lox");
+    fs::write(&path, "write(\"a\"); write(1.5); print \"b\";\n").unwrap();
+    let (stdout, stderr) = lox(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(stderr.is_empty(), "{}", stderr);
+    assert_eq!(stdout, "a1.5b\n");
+}
+
+#[test]
+fn test_eval() {
+    assert_eq!(
+        lox(&["--eval", "print 1+2;"]),
+        ("3\n".to_string(), "".into())
+    );
+    assert_eq!(lox(&["--eval", "6*7"]), ("42\n".to_string(), "".into()));
+    let (status, stdout, stderr) =
+        lox_status(&["--eval", "var x = 1; print x + nil;"]);
+    assert_eq!(status, Some(70));
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert!(stderr.contains("line 1"), "{}", stderr);
+    assert_eq!(lox_status(&["--eval", "print x"]).0, Some(65));
+    assert_eq!(lox_status(&["--eval", "print 1;"]).0, Some(0));
+}
+
+#[test]
+fn test_eprint_goes_to_stderr() {
+    let (stdout, stderr) =
+        lox(&["--eval", "eprint(\"oops\"); print \"ok\"; eprint(1);"]);
+    assert_eq!(stdout, "ok\n");
+    assert_eq!(stderr, "oops\n1\n");
+}
+
+#[test]
+fn test_runs_lox_tests() {
+    let (status, stdout, stderr) =
+        lox_status(&["--test", "examples/tests.lox"]);
+    assert_eq!(status, Some(1));
+    assert!(stdout.contains("test square of a whole number ... ok"));
+    assert!(stdout.contains("test deliberately failing ... FAILED"));
+    assert!(stdout.ends_with("2 passed; 1 failed\n"), "{}", stdout);
+    assert!(stderr.contains("assertion failed: square(3) == expected"));
+
+    let (stdout, _) = lox(&["examples/tests.lox"]);
+    assert_eq!(stdout, "tests are skipped in ordinary runs\n");
+}
+
+#[test]
+fn test_missing_test_file() {
+    let (status, stdout, stderr) =
+        lox_status(&["--test", "examples/no-such-file.lox"]);
+    assert_eq!(status, Some(1));
+    assert!(stdout.is_empty(), "{}", stdout);
+    assert!(
+        stderr.starts_with("examples/no-such-file.lox: "),
+        "{}",
+        stderr
+    );
+}